@@ -0,0 +1,53 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Fields};
+
+// newtype over a Broker, e.g. `struct GameBroker(MapBroker<Proto, Name, Error>)`
+pub fn derive_broker_impl(input: DeriveInput) -> TokenStream {
+    let fields = match input.data {
+        syn::Data::Struct(ref data) => &data.fields,
+        _ => panic!("only struct support Broker"),
+    };
+    let field = match fields.iter().collect::<Vec<_>>()[..] {
+        [field] => field,
+        _ => panic!("Broker can only be derived for a struct with exactly one field"),
+    };
+    let inner = &field.ty;
+    let wrap = match fields {
+        Fields::Named(_) => {
+            let ident = field.ident.as_ref().unwrap();
+            quote!(Self { #ident: inner })
+        }
+        _ => quote!(Self(inner)),
+    };
+    let access = match &field.ident {
+        Some(ident) => quote!(self.#ident),
+        None => quote!(self.0),
+    };
+    let ident = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::gsfw::chanrpc::broker::Broker for #ident #ty_generics #where_clause {
+            type Proto = <#inner as ::gsfw::chanrpc::broker::Broker>::Proto;
+            type Name = <#inner as ::gsfw::chanrpc::broker::Broker>::Name;
+            type Err = <#inner as ::gsfw::chanrpc::broker::Broker>::Err;
+
+            fn new(
+                name: Self::Name,
+                router: &::gsfw::chanrpc::Router<Self::Proto, Self::Name, Self::Err>,
+            ) -> Self {
+                let inner = <#inner as ::gsfw::chanrpc::broker::Broker>::new(name, router);
+                #wrap
+            }
+
+            fn name(&self) -> Self::Name {
+                ::gsfw::chanrpc::broker::Broker::name(&#access)
+            }
+
+            fn router(&self) -> &::gsfw::chanrpc::Router<Self::Proto, Self::Name, Self::Err> {
+                ::gsfw::chanrpc::broker::Broker::router(&#access)
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,182 @@
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use std::collections::HashSet;
+use syn::{AttributeArgs, FnArg, ImplItem, ImplItemMethod, ItemImpl};
+
+#[derive(FromMeta)]
+struct HandlersOps {
+    proto: syn::Path,             // the Proto enum the handlers match on
+    shutdown: Option<syn::Ident>, // variant ending the loop, `Shutdown` if omitted
+    mailbox: Option<syn::Ident>,  // field holding the mailbox, `rx` if omitted
+}
+
+// a method marked #[handler(Variant)] or #[fallback]
+struct Handler {
+    variant: Option<syn::Ident>,
+    method: syn::Ident,
+    // inputs besides self, the payload then the responder
+    inputs: usize,
+}
+
+pub fn handlers_impl(args: AttributeArgs, mut input: ItemImpl) -> TokenStream {
+    let ops = HandlersOps::from_list(&args).expect("fail to parse handlers attributes.");
+    let mut handlers = Vec::new();
+    for item in input.items.iter_mut() {
+        if let ImplItem::Method(method) = item {
+            if let Some(handler) = take_handler(method) {
+                handlers.push(handler);
+            }
+        }
+    }
+    let (fallbacks, handlers): (Vec<_>, Vec<_>) =
+        handlers.into_iter().partition(|h| h.variant.is_none());
+    if fallbacks.len() > 1 {
+        panic!("only one method can be marked with #[fallback]");
+    }
+
+    let proto = &ops.proto;
+    let shutdown = ops.shutdown.unwrap_or_else(|| format_ident!("Shutdown"));
+    let mailbox = ops.mailbox.unwrap_or_else(|| format_ident!("rx"));
+    let mut on_shutdown = quote!();
+    let mut arms = Vec::new();
+    let mut handled = HashSet::new();
+    for handler in &handlers {
+        let variant = handler.variant.as_ref().unwrap();
+        if !handled.insert(variant) {
+            panic!("variant {} has more than one handler", variant);
+        }
+        if *variant == shutdown {
+            if handler.inputs != 0 {
+                panic!("shutdown handler {} takes no payload", handler.method);
+            }
+            on_shutdown = call(handler);
+            continue;
+        }
+        let pattern = match handler.inputs {
+            0 => quote!(#proto::#variant { .. }),
+            _ => quote!(#proto::#variant(payload)),
+        };
+        let call = call(handler);
+        arms.push(quote!(#pattern => {
+            #call;
+            false
+        }));
+    }
+    if let Some(fallback) = fallbacks.first() {
+        let call = call(fallback);
+        arms.push(quote!(payload => {
+            #call;
+            false
+        }));
+    }
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+    let self_ty = &input.self_ty;
+
+    quote! {
+        #input
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Receive from the mailbox and dispatch to the handlers until the shutdown
+            /// message arrives or the mailbox closes
+            pub async fn dispatch(&mut self) {
+                while let Some(ctx) = self.#mailbox.recv().await {
+                    // handlers run in the span of the message, their sends keep its correlation id
+                    let trace = ctx.trace().clone();
+                    let (payload, responder) = ctx.into_parts();
+                    let shutdown = trace
+                        .scope(async {
+                            match payload {
+                                #proto::#shutdown => {
+                                    #on_shutdown;
+                                    true
+                                }
+                                #(#arms)*
+                            }
+                        })
+                        .await;
+                    if shutdown {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    .into()
+}
+
+// strip the handler attribute of `method`
+fn take_handler(method: &mut ImplItemMethod) -> Option<Handler> {
+    let pos = method
+        .attrs
+        .iter()
+        .position(|attr| attr.path.is_ident("handler") || attr.path.is_ident("fallback"))?;
+    let attr = method.attrs.remove(pos);
+    let ident = &method.sig.ident;
+    if method.sig.asyncness.is_none() {
+        panic!("handler {} must be an async fn", ident);
+    }
+    if !matches!(method.sig.inputs.first(), Some(FnArg::Receiver(recv)) if recv.mutability.is_some())
+    {
+        panic!("handler {} must take &mut self", ident);
+    }
+    let inputs = method.sig.inputs.len() - 1;
+    if inputs > 2 {
+        panic!(
+            "handler {} takes at most the payload and the responder",
+            ident
+        );
+    }
+    if inputs == 2 && !matches!(method.sig.output, syn::ReturnType::Default) {
+        panic!(
+            "handler {} takes the responder and must reply itself",
+            ident
+        );
+    }
+    let variant = match attr.path.is_ident("handler") {
+        true => Some(
+            attr.parse_args::<syn::Ident>()
+                .expect("expect #[handler(Variant)]"),
+        ),
+        false if inputs == 0 => panic!("fallback {} must take the payload", ident),
+        false => None,
+    };
+    Some(Handler {
+        variant,
+        method: ident.clone(),
+        inputs,
+    })
+}
+
+// await the handler, replying with its return value unless it takes the responder
+fn call(handler: &Handler) -> TokenStream2 {
+    let method = &handler.method;
+    match handler.inputs {
+        0 => quote!(::gsfw::chanrpc::IntoReply::reply(self.#method().await, responder)),
+        1 => quote!(::gsfw::chanrpc::IntoReply::reply(self.#method(payload).await, responder)),
+        _ => quote!(self.#method(payload, responder).await),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::handlers_impl;
+
+    #[test]
+    #[should_panic(expected = "variant Tick has more than one handler")]
+    fn duplicate_handler() {
+        handlers_impl(
+            vec![syn::parse_quote!(proto = "P")],
+            syn::parse_quote! {
+                impl Counter {
+                    #[handler(Tick)]
+                    async fn tick(&mut self) {}
+
+                    #[handler(Tick)]
+                    async fn tock(&mut self) {}
+                }
+            },
+        );
+    }
+}
@@ -1,9 +1,13 @@
+mod broker;
 mod dirty;
+mod handler;
+mod name;
+mod proto;
 mod registry;
 mod protocol;
 
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, AttributeArgs, DeriveInput, ItemImpl};
 
 #[proc_macro_derive(Dirty, attributes(dirty))]
 pub fn derive_dirty(input: TokenStream) -> TokenStream {
@@ -22,3 +26,36 @@ pub fn derive_registry(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     registry::derive_registry_impl(input)
 }
+
+#[proc_macro_derive(Broker)]
+pub fn derive_broker(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    broker::derive_broker_impl(input)
+}
+
+/// `impl Name`, `#[name(display)]` on a unit enum also implements Display and FromStr
+#[proc_macro_derive(Name, attributes(name))]
+pub fn derive_name(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    name::derive_name_impl(input)
+}
+
+/// `impl Proto` with the `#[proto(shutdown)]` variant, plus From/TryFrom for every
+/// single field variant not marked `#[proto(skip)]`. `#[proto(response = "Type")]` makes the
+/// payload of a variant a `Request` answered with `Type`
+#[proc_macro_derive(Proto, attributes(proto))]
+pub fn derive_proto(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    proto::derive_proto_impl(input)
+}
+
+/// Generate `async fn dispatch(&mut self)` on an impl block of a component, matching every
+/// message of `proto` to the method marked `#[handler(Variant)]` or the `#[fallback]`.
+/// Handlers returning `Result` reply with it, handlers taking the `Responder` reply themselves.
+/// A handler taking the payload needs a single field variant, each variant has one handler
+#[proc_macro_attribute]
+pub fn handlers(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as ItemImpl);
+    handler::handlers_impl(args, input)
+}
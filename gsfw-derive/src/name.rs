@@ -0,0 +1,65 @@
+use darling::FromDeriveInput;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+#[derive(FromDeriveInput, Default)]
+#[darling(attributes(name), default)]
+struct DeriveOps {
+    display: bool, // also implement Display and FromStr with the variant names
+}
+
+pub fn derive_name_impl(input: DeriveInput) -> TokenStream {
+    let ops = DeriveOps::from_derive_input(&input).expect("fail to parse derive attributes.");
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let display = if ops.display {
+        display_codegen(&input)
+    } else {
+        quote!()
+    };
+    quote! {
+        impl #impl_generics ::gsfw::chanrpc::Name for #ident #ty_generics #where_clause {}
+
+        #display
+    }
+    .into()
+}
+
+// names travel as text over remote links
+fn display_codegen(input: &DeriveInput) -> proc_macro2::TokenStream {
+    let data = match &input.data {
+        syn::Data::Enum(data) => data,
+        _ => panic!("#[name(display)] can only be used with enum"),
+    };
+    if data
+        .variants
+        .iter()
+        .any(|v| !matches!(v.fields, syn::Fields::Unit))
+    {
+        panic!("#[name(display)] requires every variant to be a unit variant");
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let variants: Vec<_> = data.variants.iter().map(|v| &v.ident).collect();
+    let texts: Vec<_> = variants.iter().map(|v| v.to_string()).collect();
+    quote! {
+        impl #impl_generics ::std::fmt::Display for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(match self {
+                    #(Self::#variants => #texts),*
+                })
+            }
+        }
+
+        impl #impl_generics ::std::str::FromStr for #ident #ty_generics #where_clause {
+            type Err = ::gsfw::error::Error;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    #(#texts => Ok(Self::#variants),)*
+                    _ => Err(::gsfw::error::Error::UnknownComponent(s.to_string())),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use darling::FromVariant;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{DeriveInput, Fields, Variant};
+
+#[derive(FromVariant, Default)]
+#[darling(attributes(proto), default)]
+struct VariantOps {
+    shutdown: bool,              // the message sent to stop a component
+    skip: bool,                  // no From/TryFrom for the payload of this variant
+    response: Option<syn::Type>, // the payload is a Request answered with this type
+}
+
+pub fn derive_proto_impl(input: DeriveInput) -> TokenStream {
+    let data = match &input.data {
+        syn::Data::Enum(data) => data,
+        _ => panic!("Proto derive can only be used with enum"),
+    };
+    let ops: Vec<_> = data
+        .variants
+        .iter()
+        .map(|v| {
+            let ops = VariantOps::from_variant(v).expect("fail to parse variant attributes.");
+            (v, ops)
+        })
+        .collect();
+    let shutdown = match ops
+        .iter()
+        .filter(|(_, ops)| ops.shutdown)
+        .collect::<Vec<_>>()[..]
+    {
+        [(v, _)] if matches!(v.fields, Fields::Unit) => &v.ident,
+        [_] => panic!("#[proto(shutdown)] must mark a unit variant"),
+        [] => panic!("Proto derive requires a variant marked with #[proto(shutdown)]"),
+        _ => panic!("only one variant can be marked with #[proto(shutdown)]"),
+    };
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let payloads: Vec<_> = ops
+        .iter()
+        .filter(|(_, ops)| !ops.skip)
+        .filter_map(|(v, _)| payload(v))
+        .collect();
+    let requests: TokenStream2 = ops
+        .iter()
+        .filter_map(|(v, ops)| Some((v, ops.skip, ops.response.as_ref()?)))
+        .map(|(v, skip, response)| {
+            let typ = match payload(v) {
+                Some((_, typ)) if !skip => typ,
+                _ => panic!(
+                    "#[proto(response)] on {} requires a single field variant without #[proto(skip)]",
+                    v.ident
+                ),
+            };
+            quote! {
+                impl #impl_generics ::gsfw::chanrpc::Request<#ident #ty_generics> for #typ #where_clause {
+                    type Response = #response;
+                }
+            }
+        })
+        .collect();
+    for (i, (v, typ)) in payloads.iter().enumerate() {
+        let typ = quote!(#typ).to_string();
+        if let Some((other, _)) = payloads[..i]
+            .iter()
+            .find(|(_, other)| quote!(#other).to_string() == typ)
+        {
+            panic!(
+                "variants {} and {} both hold {}, mark one of them with #[proto(skip)]",
+                other.ident, v.ident, typ
+            );
+        }
+    }
+    let conversions: TokenStream2 = payloads
+        .iter()
+        .map(|(v, typ)| {
+            let vident = &v.ident;
+            quote! {
+                impl #impl_generics From<#typ> for #ident #ty_generics #where_clause {
+                    fn from(inner: #typ) -> Self {
+                        Self::#vident(inner)
+                    }
+                }
+
+                impl #impl_generics TryFrom<#ident #ty_generics> for #typ #where_clause {
+                    type Error = ::gsfw::error::Error;
+                    fn try_from(proto: #ident #ty_generics) -> Result<Self, Self::Error> {
+                        if let #ident::#vident(inner) = proto {
+                            Ok(inner)
+                        } else {
+                            Err(::gsfw::error::Error::VariantCast(stringify!(#typ)))
+                        }
+                    }
+                }
+            }
+        })
+        .collect();
+
+    quote! {
+        impl #impl_generics ::gsfw::chanrpc::Proto for #ident #ty_generics #where_clause {
+            fn proto_shutdown() -> Self {
+                Self::#shutdown
+            }
+        }
+
+        #conversions
+
+        #requests
+    }
+    .into()
+}
+
+// type held by a single field tuple variant
+fn payload(var: &Variant) -> Option<(&Variant, &syn::Type)> {
+    match &var.fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            Some((var, &fields.unnamed.first().unwrap().ty))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::derive_proto_impl;
+
+    #[test]
+    #[should_panic(expected = "variants Level and Bonus both hold Level")]
+    fn duplicate_payload() {
+        derive_proto_impl(syn::parse_quote! {
+            enum Msg {
+                #[proto(shutdown)]
+                Shutdown,
+                Level(Level),
+                Bonus(Level),
+            }
+        });
+    }
+}
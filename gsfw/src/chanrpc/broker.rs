@@ -1,7 +1,15 @@
-use super::{calltx::CallTx, casttx::CastTx, ChanCtx};
+use super::{
+    calltx::CallTx, casttx::CastTx, CallError, CastError, CastReport, ChanCtx, MailboxTx,
+    ReplyStream, Router,
+};
+use crate::error::Error;
 use async_trait::async_trait;
-use std::collections::HashMap;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+type Tx<B> = MailboxTx<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>;
+type Cast<B> = CastTx<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>;
+type Call<B> = CallTx<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>;
 
 #[async_trait]
 pub trait Broker {
@@ -9,52 +17,270 @@ pub trait Broker {
     type Name: super::Name;
     type Err: Send;
 
-    fn new(
-        name: Self::Name,
-        tx_map: &HashMap<Self::Name, mpsc::Sender<ChanCtx<Self::Proto, Self::Name, Self::Err>>>,
-    ) -> Self;
+    fn new(name: Self::Name, router: &Router<Self::Proto, Self::Name, Self::Err>) -> Self;
     fn name(&self) -> Self::Name;
-    fn tx(&self, name: Self::Name) -> &mpsc::Sender<ChanCtx<Self::Proto, Self::Name, Self::Err>>;
+    fn router(&self) -> &Router<Self::Proto, Self::Name, Self::Err>;
+
+    // look up the mailbox of `name` in the routing table
+    fn tx(&self, name: Self::Name) -> Result<Tx<Self>, Error> {
+        self.router().get(&name)
+    }
+
+    fn cast_tx(&self, name: Self::Name) -> Result<Cast<Self>, Error> {
+        Ok(CastTx::new(self.name(), self.tx(name)?))
+    }
+
+    fn call_tx(&self, name: Self::Name) -> Result<Call<Self>, Error> {
+        Ok(CallTx::new(self.name(), self.tx(name)?))
+    }
+
+    /// queue `msg` in the mailbox of `to` according to its overflow policy
+    async fn cast(&self, to: Self::Name, msg: Self::Proto) -> Result<(), CastError<Self::Proto>> {
+        match self.cast_tx(to) {
+            Ok(tx) => tx.cast(msg).await,
+            Err(err) => Err(CastError::Route(err, msg)),
+        }
+    }
+
+    fn blocking_cast(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+    ) -> Result<(), CastError<Self::Proto>> {
+        match self.cast_tx(to) {
+            Ok(tx) => tx.blocking_cast(msg),
+            Err(err) => Err(CastError::Route(err, msg)),
+        }
+    }
+
+    /// queue `msg` in the system lane of `to`, received before its ordinary messages
+    fn system_cast(&self, to: Self::Name, msg: Self::Proto) -> Result<(), CastError<Self::Proto>> {
+        match self.cast_tx(to) {
+            Ok(tx) => tx.system_cast(msg),
+            Err(err) => Err(CastError::Route(err, msg)),
+        }
+    }
+
+    /// queue `msg` without waiting, the message comes back if the mailbox of `to` is full
+    fn try_cast(&self, to: Self::Name, msg: Self::Proto) -> Result<(), CastError<Self::Proto>> {
+        match self.cast_tx(to) {
+            Ok(tx) => tx.try_cast(msg),
+            Err(err) => Err(CastError::Route(err, msg)),
+        }
+    }
+
+    /// wait for room in the mailbox of `to` until `deadline`, the cast expires at `deadline`
+    async fn cast_with_deadline(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+        deadline: Instant,
+    ) -> Result<(), CastError<Self::Proto>> {
+        match self.cast_tx(to) {
+            Ok(tx) => tx.cast_with_deadline(msg, deadline).await,
+            Err(err) => Err(CastError::Route(err, msg)),
+        }
+    }
+
+    /// cast `msg` to every component of `to` concurrently
+    async fn multicast(&self, to: Vec<Self::Name>, msg: Self::Proto) -> CastReport<Self::Name>
+    where
+        Self::Proto: Clone,
+    {
+        let targets = to
+            .into_iter()
+            .map(|name| (name, ChanCtx::new_cast(msg.clone(), self.name())))
+            .collect();
+        self.router().send_each(targets).await
+    }
+
+    /// cast `msg` to every routable component, this one included
+    async fn broadcast(&self, msg: Self::Proto) -> CastReport<Self::Name>
+    where
+        Self::Proto: Clone,
+    {
+        let to = self.router().names();
+        self.multicast(to, msg).await
+    }
+
+    /// cast `msg` to every routable component except this one
+    async fn broadcast_others(&self, msg: Self::Proto) -> CastReport<Self::Name>
+    where
+        Self::Proto: Clone,
+    {
+        let from = self.name();
+        let to = self
+            .router()
+            .names()
+            .into_iter()
+            .filter(|name| *name != from)
+            .collect();
+        self.multicast(to, msg).await
+    }
+
+    async fn call(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+    ) -> Result<Self::Proto, CallError<Self::Err>> {
+        self.call_tx(to)?.call(msg).await
+    }
+
+    /// call `to`, giving up once `timeout` elapsed
+    async fn call_timeout(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+        timeout: Duration,
+    ) -> Result<Self::Proto, CallError<Self::Err>> {
+        self.call_tx(to)?.call_timeout(msg, timeout).await
+    }
+
+    /// call `to` with a typed request, a reply of another variant fails with
+    /// [`CallError::Mismatch`]
+    async fn request<R>(&self, to: Self::Name, req: R) -> Result<R::Response, CallError<Self::Err>>
+    where
+        R: super::Request<Self::Proto> + Send,
+    {
+        self.call_tx(to)?.request(req).await
+    }
 
-    fn cast_tx(&self, name: Self::Name) -> CastTx<Self::Proto, Self::Name, Self::Err> {
-        CastTx::new(self.name(), self.tx(name).clone())
+    /// call `to` for a stream of replies, see [`CallTx::call_stream`]
+    async fn call_stream(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+        buffer: usize,
+    ) -> Result<ReplyStream<Self::Proto, Self::Err>, CallError<Self::Err>> {
+        self.call_tx(to)?.call_stream(msg, buffer).await
     }
 
-    fn call_tx(&self, name: Self::Name) -> CallTx<Self::Proto, Self::Name, Self::Err> {
-        CallTx::new(self.name(), self.tx(name).clone())
+    /// call `to` through its system lane
+    async fn system_call(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+    ) -> Result<Self::Proto, CallError<Self::Err>> {
+        self.call_tx(to)?.system_call(msg).await
     }
 
-    async fn cast(&self, to: Self::Name, msg: Self::Proto) {
-        let tx = self.tx(to);
-        if let Err(err) = tx.send(ChanCtx::new_cast(msg, self.name())).await {
-            tracing::error!("fail to cast. {}", err)
+    fn blocking_call(
+        &self,
+        to: Self::Name,
+        msg: Self::Proto,
+    ) -> Result<Self::Proto, CallError<Self::Err>> {
+        self.call_tx(to)?.blocking_call(msg)
+    }
+}
+
+/// Broker routing through the shared [`Router`], for any proto and name.
+///
+/// Wrap it in a newtype to give it a name of its own, with `#[derive(gsfw::Broker)]`
+/// when the `derive` feature is on:
+///
+/// ```ignore
+/// #[derive(gsfw::Broker)]
+/// pub struct GameBroker(MapBroker<Proto, Name, Error>);
+/// ```
+pub struct MapBroker<P, N, E> {
+    name: N,
+    router: Router<P, N, E>,
+}
+
+impl<P, N: Clone, E> Clone for MapBroker<P, N, E> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            router: self.router.clone(),
         }
     }
+}
 
-    fn blocking_cast(&self, to: Self::Name, msg: Self::Proto) {
-        let tx = self.tx(to);
-        if let Err(err) = tx.blocking_send(ChanCtx::new_cast(msg, self.name())) {
-            tracing::error!("fail to cast. {}", err)
+impl<P, N: std::fmt::Debug, E> std::fmt::Debug for MapBroker<P, N, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MapBroker")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl<P, N, E> Broker for MapBroker<P, N, E>
+where
+    P: super::Proto,
+    N: super::Name,
+    E: Send,
+{
+    type Proto = P;
+    type Name = N;
+    type Err = E;
+
+    fn new(name: N, router: &Router<P, N, E>) -> Self {
+        Self {
+            name,
+            router: router.clone(),
         }
     }
 
-    async fn call(&self, to: Self::Name, msg: Self::Proto) -> Result<Self::Proto, Self::Err> {
-        let (ctx, rx) = ChanCtx::new_call(msg, self.name());
-        let tx = self.tx(to);
-        if let Err(err) = tx.send(ctx).await {
-            tracing::error!("fail to request. {}", err);
-            panic!("{}", err);
+    fn name(&self) -> N {
+        self.name.clone()
+    }
+
+    fn router(&self) -> &Router<P, N, E> {
+        &self.router
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Broker;
+    use crate::{
+        chanrpc::{
+            mailbox,
+            test_util::{Bk, N, P},
+            CastError, MailboxConfig, Router,
+        },
+        error::Error,
+    };
+
+    #[tokio::test]
+    async fn map_broker_routes() {
+        let router = Router::<P, N, ()>::default();
+        let (tx, mut rx) = mailbox::channel(&MailboxConfig::default(), "player".to_string());
+        router.insert(N::Player, tx).unwrap();
+        let broker = Bk::new(N::Gate, &router);
+
+        broker.cast(N::Player, P::Ping(1)).await.unwrap();
+        let ctx = rx.recv().await.unwrap();
+        assert_eq!(*ctx.from(), N::Gate);
+        assert_eq!(*ctx.payload(), P::Ping(1));
+
+        match broker.cast(N::Db, P::Ping(2)).await {
+            Err(CastError::Route(Error::UnknownComponent(_), msg)) => assert_eq!(msg, P::Ping(2)),
+            ret => panic!("unexpected {:?}", ret),
         }
-        rx.await.expect("blocking_recv reply error")
+        assert!(broker.call_tx(N::Db).is_err());
     }
 
-    fn blocking_call(&self, to: Self::Name, msg: Self::Proto) -> Result<Self::Proto, Self::Err> {
-        let (ctx, rx) = ChanCtx::new_call(msg, self.name());
-        let tx = self.tx(to);
-        if let Err(err) = tx.blocking_send(ctx) {
-            tracing::error!("fail to request. {}", err);
-            panic!("{}", err);
+    #[tokio::test]
+    async fn broadcast_reaches_every_component() {
+        let router = Router::<P, N, ()>::default();
+        let mut rxs = Vec::new();
+        for name in [N::Gate, N::Player, N::Db] {
+            let (tx, rx) = mailbox::channel(&MailboxConfig::default(), name.to_string());
+            router.insert(name.clone(), tx).unwrap();
+            rxs.push((name, rx));
+        }
+        let broker = Bk::new(N::Gate, &router);
+
+        assert_eq!(broker.broadcast(P::Tick).await.sent, 3);
+        assert_eq!(broker.broadcast_others(P::Ping(1)).await.sent, 2);
+        for (name, mut rx) in rxs {
+            assert_eq!(*rx.recv().await.unwrap().payload(), P::Tick);
+            let others = rx.try_recv().map(|ctx| ctx.into_payload());
+            match name {
+                N::Gate => assert!(others.is_err()),
+                _ => assert_eq!(others.unwrap(), P::Ping(1)),
+            }
         }
-        rx.blocking_recv().expect("blocking_recv reply error")
     }
 }
@@ -1,37 +1,284 @@
-use super::ChanCtx;
-use tokio::sync::{mpsc, oneshot};
+use super::{CallError, ChanCtx, MailboxTx, ReplyStream, Request};
+use std::time::Duration;
+use tokio::{sync::oneshot, time::Instant};
 
 pub struct CallTx<P, N, E> {
     // name of caller
     from: N,
     // send of call to
-    tx: mpsc::Sender<ChanCtx<P, N, E>>,
+    tx: MailboxTx<P, N, E>,
 }
 
 impl<P, N, E> CallTx<P, N, E> {
-    pub fn new(from: N, tx: mpsc::Sender<ChanCtx<P, N, E>>) -> Self {
+    pub fn new(from: N, tx: MailboxTx<P, N, E>) -> Self {
         Self { from, tx }
     }
 }
 
+// map the reply channel outcome to the call result
+fn reply<P, E>(
+    ret: Result<Result<P, CallError<E>>, oneshot::error::RecvError>,
+) -> Result<P, CallError<E>> {
+    ret.unwrap_or(Err(CallError::NoReply))
+}
+
 impl<P, N, E> CallTx<P, N, E>
 where
     P: super::Proto,
     N: super::Name,
 {
-    pub async fn call(&self, msg: P) -> oneshot::Receiver<Result<P, E>> {
+    // record the round trip of a call that got a reply
+    fn replied(&self, started: Instant, ret: &Result<P, CallError<E>>) {
+        if matches!(ret, Ok(_) | Err(CallError::Callee(_))) {
+            self.tx.record_latency(&self.from, started.elapsed());
+        }
+    }
+
+    async fn roundtrip(
+        &self,
+        ctx: ChanCtx<P, N, E>,
+        rx: oneshot::Receiver<Result<P, CallError<E>>>,
+    ) -> Result<P, CallError<E>> {
+        let started = Instant::now();
+        if self.tx.send(ctx).await.is_err() {
+            return Err(CallError::MailboxClosed);
+        }
+        let ret = reply(rx.await);
+        self.replied(started, &ret);
+        ret
+    }
+
+    pub async fn call(&self, msg: P) -> Result<P, CallError<E>> {
+        let (ctx, rx) = ChanCtx::new_call(msg, self.from.clone());
+        self.roundtrip(ctx, rx).await
+    }
+
+    /// Call, giving up once `timeout` elapsed, including the time waiting for mailbox
+    /// capacity. The call carries the same deadline, so a callee that drops expired
+    /// messages skips it.
+    pub async fn call_timeout(&self, msg: P, timeout: Duration) -> Result<P, CallError<E>> {
+        let deadline = Instant::now() + timeout;
         let (ctx, rx) = ChanCtx::new_call(msg, self.from.clone());
-        if let Err(err) = self.tx.send(ctx).await {
-            tracing::error!("fail to call. {}", err);
+        tokio::time::timeout_at(deadline, self.roundtrip(ctx.with_deadline(deadline), rx))
+            .await
+            .unwrap_or(Err(CallError::Timeout(timeout)))
+    }
+
+    /// call through the system lane of the callee, e.g. a health check that must not
+    /// wait behind the regular traffic
+    pub async fn system_call(&self, msg: P) -> Result<P, CallError<E>> {
+        let started = Instant::now();
+        let (ctx, rx) = ChanCtx::new_call(msg, self.from.clone());
+        if self.tx.send_system(ctx).is_err() {
+            return Err(CallError::MailboxClosed);
+        }
+        let ret = reply(rx.await);
+        self.replied(started, &ret);
+        ret
+    }
+
+    /// call with a typed request, the reply is converted to its response type
+    pub async fn request<R: Request<P>>(&self, req: R) -> Result<R::Response, CallError<E>> {
+        let reply = self.call(req.into()).await?;
+        R::Response::try_from(reply)
+            .map_err(|_| CallError::Mismatch(std::any::type_name::<R::Response>()))
+    }
+
+    /// call answered by a stream of items, the callee waits while `buffer` items are
+    /// unread, at least one. Dropping the stream cancels the call
+    pub async fn call_stream(
+        &self,
+        msg: P,
+        buffer: usize,
+    ) -> Result<ReplyStream<P, E>, CallError<E>> {
+        let (ctx, stream) = ChanCtx::new_stream(msg, self.from.clone(), buffer);
+        if self.tx.send(ctx).await.is_err() {
+            return Err(CallError::MailboxClosed);
         }
-        rx
+        Ok(stream)
     }
 
-    pub fn blocking_call(&self, msg: P) -> oneshot::Receiver<Result<P, E>> {
+    pub fn blocking_call(&self, msg: P) -> Result<P, CallError<E>> {
+        let started = Instant::now();
         let (ctx, rx) = ChanCtx::new_call(msg, self.from.clone());
-        if let Err(err) = self.tx.blocking_send(ctx) {
-            tracing::error!("fail to call. {}", err);
+        if self.tx.blocking_send(ctx).is_err() {
+            return Err(CallError::MailboxClosed);
+        }
+        let ret = reply(rx.blocking_recv());
+        self.replied(started, &ret);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CallTx;
+    use crate::chanrpc::{
+        mailbox,
+        test_util::{N, P},
+        CallError, IntoReply, MailboxConfig, Proto, Request,
+    };
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    enum Rpc {
+        Shutdown,
+        GetLevel(GetLevel),
+        Level(u32),
+    }
+
+    impl Proto for Rpc {
+        fn proto_shutdown() -> Self {
+            Rpc::Shutdown
         }
-        rx
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct GetLevel(u32);
+
+    impl From<GetLevel> for Rpc {
+        fn from(req: GetLevel) -> Self {
+            Rpc::GetLevel(req)
+        }
+    }
+
+    impl TryFrom<Rpc> for u32 {
+        type Error = ();
+
+        fn try_from(rpc: Rpc) -> Result<Self, Self::Error> {
+            match rpc {
+                Rpc::Level(level) => Ok(level),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl Request<Rpc> for GetLevel {
+        type Response = u32;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn call_errors() {
+        let (tx, mut rx) = mailbox::channel::<P, N, &str>(&MailboxConfig::default(), "a".into());
+        let call_tx = CallTx::new(N::Gate, tx);
+        tokio::spawn(async move {
+            while let Some(ctx) = rx.recv().await {
+                match ctx.into_parts() {
+                    (P::Ping(1), responder) => responder.ok(P::Pong(2)),
+                    (P::Ping(2), responder) => responder.err("bad request"),
+                    // never replies
+                    (P::Ping(3), responder) => drop(responder),
+                    // replies too late
+                    (_, responder) => {
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                        responder.ok(P::Tick);
+                    }
+                }
+            }
+        });
+
+        assert_eq!(call_tx.call(P::Ping(1)).await.unwrap(), P::Pong(2));
+        assert!(matches!(
+            call_tx.call(P::Ping(2)).await,
+            Err(CallError::Callee("bad request"))
+        ));
+        assert!(matches!(
+            call_tx.call(P::Ping(3)).await,
+            Err(CallError::NoReply)
+        ));
+        assert!(matches!(
+            call_tx
+                .call_timeout(P::Ping(4), Duration::from_secs(1))
+                .await,
+            Err(CallError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn typed_request() {
+        let (tx, mut rx) = mailbox::channel::<Rpc, N, ()>(&MailboxConfig::default(), "a".into());
+        let call_tx = CallTx::new(N::Gate, tx);
+        tokio::spawn(async move {
+            while let Some(ctx) = rx.recv().await {
+                match ctx.into_parts() {
+                    (Rpc::GetLevel(GetLevel(0)), responder) => responder.ok(Rpc::Shutdown),
+                    (Rpc::GetLevel(GetLevel(id)), responder) => responder.ok(Rpc::Level(id * 10)),
+                    _ => (),
+                }
+            }
+        });
+
+        assert_eq!(call_tx.request(GetLevel(3)).await.unwrap(), 30);
+        assert!(matches!(
+            call_tx.request(GetLevel(0)).await,
+            Err(CallError::Mismatch("u32"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn handler_replies() {
+        let (tx, mut rx) = mailbox::channel::<P, N, &str>(&MailboxConfig::default(), "a".into());
+        let call_tx = CallTx::new(N::Gate, tx);
+        tokio::spawn(async move {
+            while let Some(ctx) = rx.recv().await {
+                match ctx.into_parts() {
+                    (P::Ping(0), responder) => IntoReply::reply(Err::<P, _>("zero"), responder),
+                    (P::Ping(n), responder) => {
+                        IntoReply::reply(Ok::<_, &str>(P::Pong(n)), responder)
+                    }
+                    (_, responder) => IntoReply::reply((), responder),
+                }
+            }
+        });
+
+        assert_eq!(call_tx.call(P::Ping(2)).await.unwrap(), P::Pong(2));
+        assert!(matches!(
+            call_tx.call(P::Ping(0)).await,
+            Err(CallError::Callee("zero"))
+        ));
+        assert!(matches!(
+            call_tx.call(P::Tick).await,
+            Err(CallError::NoReply)
+        ));
+    }
+
+    #[tokio::test]
+    async fn responder_replies_later() {
+        let (tx, mut rx) = mailbox::channel::<P, N, &str>(&MailboxConfig::default(), "a".into());
+        let call_tx = CallTx::new(N::Gate, tx);
+        tokio::spawn(async move {
+            // answer both calls once the second arrived, from another task
+            let mut pending = Vec::new();
+            while let Some(ctx) = rx.recv().await {
+                pending.push(ctx.into_parts());
+                if pending.len() == 2 {
+                    let pending = std::mem::take(&mut pending);
+                    tokio::spawn(async move {
+                        for (payload, responder) in pending {
+                            if let P::Ping(n) = payload {
+                                responder.ok(P::Pong(n * 2));
+                            }
+                        }
+                    });
+                }
+            }
+        });
+
+        let (first, second) = tokio::join!(call_tx.call(P::Ping(1)), call_tx.call(P::Ping(2)));
+        assert_eq!(first.unwrap(), P::Pong(2));
+        assert_eq!(second.unwrap(), P::Pong(4));
+    }
+
+    #[tokio::test]
+    async fn call_closed_mailbox() {
+        let (tx, rx) = mailbox::channel::<P, N, ()>(&MailboxConfig::default(), "a".into());
+        // the dropped receiver is parked in its slot, close it as a stopped component does
+        let slot = rx.slot();
+        drop(rx);
+        slot.close();
+        assert!(matches!(
+            CallTx::new(N::Gate, tx).call(P::Ping(1)).await,
+            Err(CallError::MailboxClosed)
+        ));
     }
 }
@@ -1,13 +1,13 @@
-use super::ChanCtx;
-use tokio::sync::mpsc;
+use super::{CastError, ChanCtx, MailboxTx};
+use tokio::time::Instant;
 
 pub struct CastTx<P, N, E> {
     from: N,
-    tx: mpsc::Sender<ChanCtx<P, N, E>>,
+    tx: MailboxTx<P, N, E>,
 }
 
 impl<P, N, E> CastTx<P, N, E> {
-    pub fn new(from: N, tx: mpsc::Sender<ChanCtx<P, N, E>>) -> Self {
+    pub fn new(from: N, tx: MailboxTx<P, N, E>) -> Self {
         Self { from, tx }
     }
 }
@@ -17,22 +17,76 @@ where
     P: super::Proto,
     N: super::Name,
 {
-    pub async fn cast(&self, msg: P) {
-        if let Err(err) = self
-            .tx
-            .send(ChanCtx::new_cast(msg, self.from.clone()))
+    /// queue `msg` according to the overflow policy of the target mailbox
+    pub async fn cast(&self, msg: P) -> Result<(), CastError<P>> {
+        self.tx
+            .deliver(ChanCtx::new_cast(msg, self.from.clone()))
             .await
-        {
-            tracing::error!("fail to cast. {}", err)
-        }
+            .map_err(CastError::from)
+    }
+
+    pub fn blocking_cast(&self, msg: P) -> Result<(), CastError<P>> {
+        self.tx
+            .blocking_deliver(ChanCtx::new_cast(msg, self.from.clone()))
+            .map_err(CastError::from)
+    }
+
+    /// queue `msg` in the system lane of the target, ahead of every ordinary message
+    pub fn system_cast(&self, msg: P) -> Result<(), CastError<P>> {
+        self.tx
+            .send_system(ChanCtx::new_cast(msg, self.from.clone()))
+            .map_err(CastError::from)
+    }
+
+    /// queue `msg` without waiting, a full mailbox hands it back in [`CastError::Full`]
+    pub fn try_cast(&self, msg: P) -> Result<(), CastError<P>> {
+        self.tx
+            .try_send(ChanCtx::new_cast(msg, self.from.clone()))
+            .map_err(CastError::from)
     }
 
-    pub fn blocking_cast(&self, msg: P) {
-        if let Err(err) = self
-            .tx
-            .blocking_send(ChanCtx::new_cast(msg, self.from.clone()))
-        {
-            tracing::error!("fail to cast. {}", err)
+    /// Wait for room in the target mailbox until `deadline`, whatever its overflow policy.
+    /// The cast carries the deadline, so a target that drops expired messages skips it.
+    pub async fn cast_with_deadline(&self, msg: P, deadline: Instant) -> Result<(), CastError<P>> {
+        let ctx = ChanCtx::new_cast(msg, self.from.clone()).with_deadline(deadline);
+        self.tx
+            .send_deadline(ctx, deadline)
+            .await
+            .map_err(CastError::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CastTx;
+    use crate::chanrpc::{
+        mailbox,
+        test_util::{N, P},
+        CastError, MailboxConfig, Overflow,
+    };
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn cast_backpressure() {
+        let config = MailboxConfig::bounded(1).overflow(Overflow::Reject);
+        let (tx, mut rx) = mailbox::channel::<P, N, ()>(&config, "player".to_string());
+        let cast = CastTx::new(N::Gate, tx);
+        cast.try_cast(P::Ping(1)).unwrap();
+        match cast.try_cast(P::Ping(2)) {
+            Err(CastError::Full(msg)) => assert_eq!(msg, P::Ping(2)),
+            ret => panic!("unexpected {:?}", ret),
         }
+        assert!(matches!(
+            cast.cast(P::Ping(3)).await,
+            Err(CastError::Full(_))
+        ));
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        let ret = cast.cast_with_deadline(P::Ping(4), deadline).await;
+        assert_eq!(ret.unwrap_err().into_inner(), P::Ping(4));
+
+        assert_eq!(rx.recv().await.unwrap().into_payload(), P::Ping(1));
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        cast.cast_with_deadline(P::Ping(5), deadline).await.unwrap();
     }
 }
@@ -1,25 +1,140 @@
-use std::{cell::RefCell, fmt::Debug, hash::Hash};
+use super::{
+    stream::{self, ReplyStream, StreamTx},
+    CallError, Trace,
+};
+use std::{fmt::Debug, hash::Hash};
 
-use tokio::sync::oneshot;
+use tokio::{
+    sync::{mpsc::error::TrySendError, oneshot},
+    time::Instant,
+};
 
-type ReplySender<P, E> = oneshot::Sender<Result<P, E>>;
-type ReplyReceiver<P, E> = oneshot::Receiver<Result<P, E>>;
+// the callee replies Callee errors, the mailbox answers expired calls with Expired
+type ReplySender<P, E> = oneshot::Sender<Result<P, CallError<E>>>;
+type ReplyReceiver<P, E> = oneshot::Receiver<Result<P, CallError<E>>>;
 
 pub trait Proto: Send {
     fn proto_shutdown() -> Self;
 }
 
-pub trait Name: Send + Hash + Eq + Clone + Debug {}
+/// Identifies a component. Names are kept in the [`super::Router`] every broker shares across
+/// threads, so a name must be `Sync` as well as `Send`
+pub trait Name: Send + Sync + Hash + Eq + Clone + Debug {}
 
+/// A call payload of the proto `P` tied to the payload of its reply, see
+/// [`super::broker::Broker::request`]
+pub trait Request<P>: Into<P> {
+    type Response: TryFrom<P>;
+}
+
+/// Return value of a handler generated by `#[gsfw::handlers]`, replied through the
+/// responder of the message it handled. `()` replies nothing, a call left unanswered fails
+/// with `CallError::NoReply`
+pub trait IntoReply<P, E> {
+    fn reply(self, responder: Responder<P, E>);
+}
+
+impl<P, E> IntoReply<P, E> for () {
+    fn reply(self, _: Responder<P, E>) {}
+}
+
+impl<P, E, R, F> IntoReply<P, E> for Result<R, F>
+where
+    P: Proto,
+    R: Into<P>,
+    F: Into<E>,
+{
+    fn reply(self, responder: Responder<P, E>) {
+        if !responder.is_call() {
+            if self.is_err() {
+                tracing::warn!("ChanRpc cast handler failed, nobody to reply to");
+            }
+            return;
+        }
+        match self {
+            Ok(reply) => responder.ok(reply.into()),
+            Err(err) => responder.err(err.into()),
+        }
+    }
+}
+
+// how the callee answers a call
+#[derive(Debug)]
+enum Reply<P, E> {
+    Once(ReplySender<P, E>),
+    Stream(StreamTx<P, E>),
+}
+
+/// A message in a mailbox. The receiver takes it apart with [`ChanCtx::into_parts`]
 #[derive(Debug)]
 pub struct ChanCtx<P, N, E> {
-    payload: RefCell<Option<P>>,
+    payload: P,
     from: N,
-    reply_chan: Option<ReplySender<P, E>>,
+    trace: Trace,
+    deadline: Option<Instant>,
+    reply_chan: Option<Reply<P, E>>,
 }
 
-unsafe impl<P: Send, N: Send, E: Send> Send for ChanCtx<P, N, E> {}
-unsafe impl<P: Sync, N: Sync, E:Sync> Sync for ChanCtx<P, N, E> {}
+impl<P, N, E> ChanCtx<P, N, E> {
+    pub fn from(&self) -> &N {
+        &self.from
+    }
+
+    /// true if the sender waits for a reply
+    pub fn is_call(&self) -> bool {
+        self.reply_chan.is_some()
+    }
+
+    /// the message is worthless past `deadline`, see [`super::MailboxConfig::drop_expired`]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// true once the deadline set by the sender passed
+    pub fn is_expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    /// answer an expired call with [`CallError::Expired`], the only item of a streaming call
+    pub(crate) fn expire(self) {
+        match self.reply_chan {
+            Some(Reply::Once(reply_chan)) => {
+                let _ = reply_chan.send(Err(CallError::Expired));
+            }
+            Some(Reply::Stream(tx)) => {
+                let _ = tx.try_send(Err(CallError::Expired));
+            }
+            None => (),
+        }
+    }
+
+    /// correlation id and span of the sender
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+
+    /// the payload and the responder answering the sender
+    pub fn into_parts(self) -> (P, Responder<P, E>) {
+        let responder = Responder {
+            reply_chan: self.reply_chan,
+        };
+        (self.payload, responder)
+    }
+
+    /// the payload of a cast. a call is dropped unanswered
+    pub fn into_payload(self) -> P {
+        self.into_parts().0
+    }
+}
 
 #[allow(dead_code)]
 impl<P, N, E> ChanCtx<P, N, E>
@@ -30,9 +145,26 @@ where
         let (tx, rx) = oneshot::channel();
         (
             Self {
-                payload: RefCell::new(msg.into()),
+                payload: msg,
                 from,
-                reply_chan: Some(tx),
+                trace: Trace::current(),
+                deadline: None,
+                reply_chan: Some(Reply::Once(tx)),
+            },
+            rx,
+        )
+    }
+
+    /// call answered by up to `buffer` items in flight, see [`Responder::stream`]
+    pub fn new_stream(msg: P, from: N, buffer: usize) -> (ChanCtx<P, N, E>, ReplyStream<P, E>) {
+        let (tx, rx) = stream::channel(buffer);
+        (
+            Self {
+                payload: msg,
+                from,
+                trace: Trace::current(),
+                deadline: None,
+                reply_chan: Some(Reply::Stream(tx)),
             },
             rx,
         )
@@ -40,38 +172,284 @@ where
 
     pub fn new_cast(msg: P, from: N) -> ChanCtx<P, N, E> {
         Self {
-            payload: RefCell::new(Some(msg)),
+            payload: msg,
             from,
+            trace: Trace::current(),
+            deadline: None,
             reply_chan: None,
         }
     }
 
-    pub fn from(&self) -> &N {
-        &self.from
+    /// reply without looking at the payload
+    pub fn ok(self, reply: P) {
+        self.into_parts().1.ok(reply)
     }
 
-    pub fn payload(&self) -> P {
-        let mut p = self.payload.borrow_mut();
-        p.take().expect("calling twice payload()")
+    pub fn err(self, err: E) {
+        self.into_parts().1.err(err)
     }
+}
 
-    pub fn ok(self, reply: P) {
-        if let Some(reply_chan) = self.reply_chan {
-            if let Err(_) = reply_chan.send(Ok(reply)) {
-                tracing::error!("ChanRpc fail to reply with Ok. receiver dropped");
+/// Answers the sender of a message. It can be stored and used later, e.g. after a database
+/// round-trip. Dropping the responder of a call without replying logs a warning and fails
+/// the call with `CallError::NoReply`, a streaming call gets it as its only item
+#[derive(Debug)]
+pub struct Responder<P, E> {
+    // taken by the reply
+    reply_chan: Option<Reply<P, E>>,
+}
+
+impl<P, E> Responder<P, E> {
+    /// true if the sender waits for a reply
+    pub fn is_call(&self) -> bool {
+        self.reply_chan.is_some()
+    }
+
+    /// the reply sender of a streaming call, any other responder comes back unchanged
+    pub fn stream(mut self) -> Result<StreamTx<P, E>, Self> {
+        match self.reply_chan.take() {
+            Some(Reply::Stream(tx)) => Ok(tx),
+            reply_chan => {
+                self.reply_chan = reply_chan;
+                Err(self)
             }
-            return;
         }
-        tracing::warn!("attempt to reply to a non request ctx");
+    }
+
+    // a streaming call gets the reply as its only item
+    fn reply(mut self, ret: Result<P, E>, kind: &str) {
+        match self.reply_chan.take() {
+            Some(Reply::Once(reply_chan)) => {
+                if reply_chan.send(ret.map_err(CallError::Callee)).is_err() {
+                    tracing::error!("ChanRpc fail to reply with {}. receiver dropped", kind);
+                }
+            }
+            // the stream holds at least one item and the responder is its only sender,
+            // so the reply never finds it full
+            Some(Reply::Stream(tx)) => match tx.try_send(ret.map_err(CallError::Callee)) {
+                Ok(_) => (),
+                Err(TrySendError::Full(_)) => {
+                    tracing::error!("ChanRpc fail to reply with {}. stream full", kind)
+                }
+                Err(TrySendError::Closed(_)) => {
+                    tracing::error!("ChanRpc fail to reply with {}. stream dropped", kind)
+                }
+            },
+            None => tracing::warn!("attempt to reply to a non request ctx"),
+        }
+    }
+
+    pub fn ok(self, reply: P) {
+        self.reply(Ok(reply), "Ok")
     }
 
     pub fn err(self, err: E) {
-        if let Some(reply_chan) = self.reply_chan {
-            if let Err(_) = reply_chan.send(Err(err)) {
-                tracing::error!("ChanRpc fail to reply with Err. receiver dropped");
+        self.reply(Err(err), "Err")
+    }
+}
+
+impl<P, E> Drop for Responder<P, E> {
+    fn drop(&mut self) {
+        // the caller may have given up already. the responder is the only sender of a stream
+        // it holds, so the stream has room for the error
+        match self.reply_chan.take() {
+            Some(Reply::Once(reply_chan)) if !reply_chan.is_closed() => {
+                tracing::warn!("ChanRpc call dropped without reply");
+                let _ = reply_chan.send(Err(CallError::NoReply));
             }
-            return;
+            Some(Reply::Stream(tx)) if !tx.is_closed() => {
+                tracing::warn!("ChanRpc streaming call dropped without reply");
+                let _ = tx.try_send(Err(CallError::NoReply));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Name, Proto, Responder};
+    use crate::{
+        chanrpc::{
+            mailbox,
+            test_util::{N, P},
+            CallError, CallTx, CastTx, CorrelationId, Mailbox, MailboxConfig, Trace,
+        },
+        error::Error,
+    };
+
+    struct Counter {
+        rx: Mailbox<P, N, &'static str>,
+        count: u32,
+        ticks: Vec<CorrelationId>,
+        unhandled: Vec<P>,
+        stopped: bool,
+    }
+
+    #[gsfw_derive::handlers(proto = "P")]
+    impl Counter {
+        #[handler(Tick)]
+        async fn tick(&mut self) {
+            self.count += 1;
+            self.ticks.push(CorrelationId::current());
+        }
+
+        #[handler(Get)]
+        async fn get(&mut self) -> Result<P, &'static str> {
+            Ok(P::Count(self.count))
+        }
+
+        #[handler(Ping)]
+        async fn ping(&mut self, n: u32) -> Result<P, &'static str> {
+            match n {
+                0 => Err("zero"),
+                n => Ok(P::Pong(n)),
+            }
+        }
+
+        #[handler(Count)]
+        async fn set(&mut self, count: u32, responder: Responder<P, &'static str>) {
+            self.count = count;
+            responder.ok(P::Count(count));
+        }
+
+        #[fallback]
+        async fn other(&mut self, payload: P) {
+            self.unhandled.push(payload);
+        }
+
+        #[handler(Shutdown)]
+        async fn shutdown(&mut self) {
+            self.stopped = true;
         }
-        tracing::warn!("attempt to reply to a non request ctx");
+    }
+
+    #[tokio::test]
+    async fn handlers_dispatch() {
+        let (tx, rx) = mailbox::channel(&MailboxConfig::default(), "counter".to_string());
+        let mut counter = Counter {
+            rx,
+            count: 0,
+            ticks: Vec::new(),
+            unhandled: Vec::new(),
+            stopped: false,
+        };
+        let dispatch = tokio::spawn(async move {
+            counter.dispatch().await;
+            counter
+        });
+        let call = CallTx::new(N::Test, tx.clone());
+        let cast = CastTx::new(N::Test, tx);
+
+        let trace = Trace::new(CorrelationId::from(42), None);
+        trace.scope(cast.cast(P::Tick)).await.unwrap();
+        assert_eq!(call.call(P::Get).await.unwrap(), P::Count(1));
+        assert_eq!(call.call(P::Ping(3)).await.unwrap(), P::Pong(3));
+        assert!(matches!(
+            call.call(P::Ping(0)).await,
+            Err(CallError::Callee("zero"))
+        ));
+        assert_eq!(call.call(P::Count(5)).await.unwrap(), P::Count(5));
+        // the fallback replies nothing
+        assert!(matches!(
+            call.call(P::Pong(1)).await,
+            Err(CallError::NoReply)
+        ));
+        cast.cast(P::Tick).await.unwrap();
+        cast.cast(P::Shutdown).await.unwrap();
+
+        let counter = dispatch.await.unwrap();
+        assert!(counter.stopped);
+        assert_eq!(counter.count, 6);
+        assert_eq!(counter.ticks[0], CorrelationId::from(42));
+        assert_eq!(counter.unhandled, vec![P::Pong(1)]);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, gsfw_derive::Name)]
+    #[name(display)]
+    enum Server {
+        Gate,
+        Db,
+    }
+
+    // generic names, one type per shard
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, gsfw_derive::Name)]
+    #[name(display)]
+    enum Shard<const ID: u8> {
+        Gate,
+        Db,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, gsfw_derive::Name)]
+    struct Player(u64);
+
+    #[derive(Debug, PartialEq)]
+    struct GetLevel(u64);
+
+    #[derive(Debug, PartialEq)]
+    struct Level(u32);
+
+    #[derive(Debug, PartialEq, gsfw_derive::Proto)]
+    enum Msg {
+        #[proto(shutdown)]
+        Shutdown,
+        #[proto(response = "Level")]
+        GetLevel(GetLevel),
+        Level(Level),
+        #[proto(skip)]
+        Bonus(Level),
+        Move(i32, i32),
+    }
+
+    fn is_name<T: Name>(_: &T) -> bool {
+        true
+    }
+
+    #[test]
+    fn derive_name() {
+        assert!(is_name(&Player(1)));
+        assert!(is_name(&Server::Gate));
+        assert_eq!(Server::Gate.to_string(), "Gate");
+        assert_eq!("Db".parse::<Server>().unwrap(), Server::Db);
+        assert!(matches!(
+            "Mail".parse::<Server>(),
+            Err(Error::UnknownComponent(name)) if name == "Mail"
+        ));
+        assert!(is_name(&Shard::<1>::Gate));
+        assert_eq!(Shard::<1>::Db.to_string(), "Db");
+        assert_eq!("Gate".parse::<Shard<2>>().unwrap(), Shard::Gate);
+    }
+
+    #[tokio::test]
+    async fn derive_proto() {
+        assert_eq!(Msg::proto_shutdown(), Msg::Shutdown);
+        assert_eq!(Msg::from(Level(3)), Msg::Level(Level(3)));
+        assert_eq!(Msg::from(GetLevel(7)), Msg::GetLevel(GetLevel(7)));
+        assert_eq!(Level::try_from(Msg::Level(Level(3))).unwrap(), Level(3));
+        assert!(matches!(
+            Level::try_from(Msg::Bonus(Level(1))),
+            Err(Error::VariantCast("Level"))
+        ));
+        assert!(matches!(Msg::Move(1, 2), Msg::Move(1, 2)));
+
+        let (tx, mut rx) =
+            mailbox::channel::<Msg, Player, ()>(&MailboxConfig::default(), "player".to_string());
+        tokio::spawn(async move {
+            while let Some(ctx) = rx.recv().await {
+                match ctx.into_parts() {
+                    (Msg::GetLevel(GetLevel(0)), responder) => responder.ok(Msg::Bonus(Level(0))),
+                    (Msg::GetLevel(GetLevel(id)), responder) => {
+                        responder.ok(Msg::Level(Level(id as u32 * 10)))
+                    }
+                    _ => (),
+                }
+            }
+        });
+        let call = CallTx::new(Player(1), tx);
+        assert_eq!(call.request(GetLevel(2)).await.unwrap(), Level(20));
+        assert!(matches!(
+            call.request(GetLevel(0)).await,
+            Err(CallError::Mismatch(_))
+        ));
     }
 }
@@ -0,0 +1,92 @@
+use super::{ChanCtx, Proto};
+use std::time::Duration;
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError, TrySendError};
+
+/// Why a chanrpc call produced no reply payload
+#[derive(Debug, thiserror::Error)]
+pub enum CallError<E> {
+    /// the callee is not routable, see [`crate::chanrpc::Router::get`]
+    #[error(transparent)]
+    Route(#[from] crate::error::Error),
+    #[error("mailbox of the callee is closed")]
+    MailboxClosed,
+    /// the callee dropped the request without replying
+    #[error("callee dropped the request without reply")]
+    NoReply,
+    #[error("no reply within {0:?}")]
+    Timeout(Duration),
+    /// the callee replied with an error
+    #[error("callee replied with an error")]
+    Callee(E),
+    /// the deadline of the call passed while it waited in the mailbox of the callee, see
+    /// [`crate::chanrpc::MailboxConfig::drop_expired`]
+    #[error("call expired before the callee received it")]
+    Expired,
+    /// the reply of a [`crate::chanrpc::Request`] is not its response type
+    #[error("reply is not a {0}")]
+    Mismatch(&'static str),
+}
+
+impl<E> CallError<E> {
+    /// error replied by the callee, None for delivery failures
+    pub fn into_callee(self) -> Option<E> {
+        match self {
+            Self::Callee(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The caller dropped the reply stream of a streaming call, the item comes back
+#[derive(Debug, thiserror::Error)]
+#[error("caller dropped the reply stream")]
+pub struct StreamClosed<T>(pub T);
+
+/// Why a chanrpc cast was not queued. Every variant hands the message back to the sender
+#[derive(Debug, thiserror::Error)]
+pub enum CastError<P> {
+    /// the target is not routable, see [`crate::chanrpc::Router::get`]
+    #[error("{0}")]
+    Route(crate::error::Error, P),
+    /// the mailbox of the target is full and rejects new messages
+    #[error("mailbox of the target is full")]
+    Full(P),
+    #[error("mailbox of the target is closed")]
+    Closed(P),
+    /// the mailbox of the target stayed full until the deadline
+    #[error("mailbox of the target is still full at the deadline")]
+    Timeout(P),
+}
+
+impl<P> CastError<P> {
+    /// the message that could not be cast
+    pub fn into_inner(self) -> P {
+        match self {
+            Self::Route(_, msg) | Self::Full(msg) | Self::Closed(msg) | Self::Timeout(msg) => msg,
+        }
+    }
+}
+
+impl<P: Proto, N, E> From<SendError<ChanCtx<P, N, E>>> for CastError<P> {
+    fn from(err: SendError<ChanCtx<P, N, E>>) -> Self {
+        Self::Closed(err.0.into_payload())
+    }
+}
+
+impl<P: Proto, N, E> From<TrySendError<ChanCtx<P, N, E>>> for CastError<P> {
+    fn from(err: TrySendError<ChanCtx<P, N, E>>) -> Self {
+        match err {
+            TrySendError::Full(ctx) => Self::Full(ctx.into_payload()),
+            TrySendError::Closed(ctx) => Self::Closed(ctx.into_payload()),
+        }
+    }
+}
+
+impl<P: Proto, N, E> From<SendTimeoutError<ChanCtx<P, N, E>>> for CastError<P> {
+    fn from(err: SendTimeoutError<ChanCtx<P, N, E>>) -> Self {
+        match err {
+            SendTimeoutError::Timeout(ctx) => Self::Timeout(ctx.into_payload()),
+            SendTimeoutError::Closed(ctx) => Self::Closed(ctx.into_payload()),
+        }
+    }
+}
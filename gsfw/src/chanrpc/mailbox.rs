@@ -0,0 +1,730 @@
+use super::{metrics::MailboxStats, ChanCtx};
+use std::{
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+use tokio::{
+    sync::mpsc::{
+        self,
+        error::{SendError, SendTimeoutError, TryRecvError, TrySendError},
+    },
+    time::Instant,
+};
+
+/// What a cast does when the target mailbox is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// wait for capacity
+    #[default]
+    Block,
+    /// drop the message being cast and log a warning
+    DropNewest,
+    /// fail the cast, handing the message back to the sender
+    Reject,
+}
+
+/// Channel configuration of a component's mailbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailboxConfig {
+    // None means unbounded
+    capacity: Option<usize>,
+    high_water_mark: Option<usize>,
+    overflow: Overflow,
+    drop_expired: bool,
+}
+
+impl Default for MailboxConfig {
+    fn default() -> Self {
+        Self::bounded(1024)
+    }
+}
+
+impl MailboxConfig {
+    /// senders wait once `capacity` messages are queued, at least one
+    pub fn bounded(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity.max(1)),
+            high_water_mark: None,
+            overflow: Overflow::Block,
+            drop_expired: false,
+        }
+    }
+
+    /// senders never wait, the queue grows without limit
+    pub fn unbounded() -> Self {
+        Self {
+            capacity: None,
+            high_water_mark: None,
+            overflow: Overflow::Block,
+            drop_expired: false,
+        }
+    }
+
+    /// log a warning when the queue depth reaches `mark`.
+    /// warns again after the depth fell below half of the mark, or to zero for a mark of one
+    pub fn high_water_mark(mut self, mark: usize) -> Self {
+        self.high_water_mark = Some(mark);
+        self
+    }
+
+    /// how casts behave once a bounded mailbox is full. calls always wait
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Drop messages whose deadline passed before they were received, answering expired
+    /// calls with `CallError::Expired`. Drops are counted in `MailboxMetrics::expired`
+    pub fn drop_expired(mut self, drop: bool) -> Self {
+        self.drop_expired = drop;
+        self
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+}
+
+// queue depth shared by every sender and the receiver of a mailbox
+#[derive(Debug)]
+pub(crate) struct Depth {
+    owner: String,
+    len: AtomicUsize,
+    peak: AtomicUsize,
+    high_water_mark: Option<usize>,
+    warned: AtomicBool,
+}
+
+impl Depth {
+    fn inc(&self) {
+        let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak.fetch_max(len, Ordering::Relaxed);
+        if let Some(mark) = self.high_water_mark {
+            if len >= mark && !self.warned.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    "mailbox of {} reached high water mark. depth: {}, mark: {}",
+                    self.owner,
+                    len,
+                    mark
+                );
+            }
+        }
+    }
+
+    fn dec(&self) {
+        let len = self.len.fetch_sub(1, Ordering::Relaxed) - 1;
+        if let Some(mark) = self.high_water_mark {
+            if len < (mark / 2).max(1) {
+                self.warned.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+enum Sender<T> {
+    Bounded(mpsc::Sender<T>),
+    Unbounded(mpsc::UnboundedSender<T>),
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Bounded(tx) => Self::Bounded(tx.clone()),
+            Self::Unbounded(tx) => Self::Unbounded(tx.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Receiver<T> {
+    Bounded(mpsc::Receiver<T>),
+    Unbounded(mpsc::UnboundedReceiver<T>),
+}
+
+impl<T> Receiver<T> {
+    async fn recv(&mut self) -> Option<T> {
+        match self {
+            Self::Bounded(rx) => rx.recv().await,
+            Self::Unbounded(rx) => rx.recv().await,
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        match self {
+            Self::Bounded(rx) => rx.try_recv(),
+            Self::Unbounded(rx) => rx.try_recv(),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Self::Bounded(rx) => rx.close(),
+            Self::Unbounded(rx) => rx.close(),
+        }
+    }
+}
+
+// Both lanes of a mailbox. The system lane is unbounded, so shutdown and other system
+// messages never wait behind a full normal lane.
+#[derive(Debug)]
+struct Lanes<T> {
+    system: mpsc::UnboundedReceiver<T>,
+    normal: Receiver<T>,
+    depth: Arc<Depth>,
+}
+
+impl<T> Drop for Lanes<T> {
+    // the messages still queued are dropped with the lanes, they leave the depth
+    fn drop(&mut self) {
+        self.system.close();
+        self.normal.close();
+        while self.system.try_recv().is_ok() {
+            self.depth.dec();
+        }
+        while self.normal.try_recv().is_ok() {
+            self.depth.dec();
+        }
+    }
+}
+
+type Ctx<P, N, E> = ChanCtx<P, N, E>;
+
+/// Sending side of a component's mailbox, handed to brokers
+#[derive(Debug)]
+pub struct MailboxTx<P, N, E> {
+    tx: Sender<Ctx<P, N, E>>,
+    system: mpsc::UnboundedSender<Ctx<P, N, E>>,
+    depth: Arc<Depth>,
+    stats: Arc<MailboxStats<N>>,
+    overflow: Overflow,
+}
+
+impl<P, N, E> Clone for MailboxTx<P, N, E> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            system: self.system.clone(),
+            depth: self.depth.clone(),
+            stats: self.stats.clone(),
+            overflow: self.overflow,
+        }
+    }
+}
+
+impl<P, N, E> MailboxTx<P, N, E>
+where
+    N: Hash + Eq + Clone,
+{
+    // count the message as queued before sending, so senders waiting for capacity show in
+    // the depth. returns what `sent` needs to record the outcome
+    fn sending(&self, ctx: &Ctx<P, N, E>) -> (N, bool) {
+        self.depth.inc();
+        (ctx.from().clone(), ctx.is_call())
+    }
+
+    fn sent<T>(&self, (from, call): (N, bool), ret: &Result<(), T>) {
+        match ret {
+            Ok(_) => self.stats.record_message(&from, call),
+            Err(_) => self.depth.dec(),
+        }
+    }
+
+    pub async fn send(&self, ctx: Ctx<P, N, E>) -> Result<(), SendError<Ctx<P, N, E>>> {
+        let sender = self.sending(&ctx);
+        let ret = match &self.tx {
+            Sender::Bounded(tx) => tx.send(ctx).await,
+            Sender::Unbounded(tx) => tx.send(ctx),
+        };
+        self.sent(sender, &ret);
+        ret
+    }
+
+    pub fn blocking_send(&self, ctx: Ctx<P, N, E>) -> Result<(), SendError<Ctx<P, N, E>>> {
+        let sender = self.sending(&ctx);
+        let ret = match &self.tx {
+            Sender::Bounded(tx) => tx.blocking_send(ctx),
+            Sender::Unbounded(tx) => tx.send(ctx),
+        };
+        self.sent(sender, &ret);
+        ret
+    }
+
+    pub fn try_send(&self, ctx: Ctx<P, N, E>) -> Result<(), TrySendError<Ctx<P, N, E>>> {
+        let sender = self.sending(&ctx);
+        let ret = match &self.tx {
+            Sender::Bounded(tx) => tx.try_send(ctx),
+            Sender::Unbounded(tx) => tx.send(ctx).map_err(|err| TrySendError::Closed(err.0)),
+        };
+        self.sent(sender, &ret);
+        ret
+    }
+
+    /// Queue `ctx` in the system lane, received before any message of the normal lane.
+    /// Never waits, meant for shutdown, health checks and the like.
+    pub fn send_system(&self, ctx: Ctx<P, N, E>) -> Result<(), SendError<Ctx<P, N, E>>> {
+        let sender = self.sending(&ctx);
+        let ret = self.system.send(ctx);
+        self.sent(sender, &ret);
+        ret
+    }
+
+    /// wait for capacity until `deadline`
+    pub async fn send_deadline(
+        &self,
+        ctx: Ctx<P, N, E>,
+        deadline: Instant,
+    ) -> Result<(), SendTimeoutError<Ctx<P, N, E>>> {
+        let tx = match &self.tx {
+            Sender::Bounded(tx) => tx,
+            Sender::Unbounded(_) => {
+                return self
+                    .try_send(ctx)
+                    .map_err(|err| SendTimeoutError::Closed(err.into_inner()))
+            }
+        };
+        let permit = match tokio::time::timeout_at(deadline, tx.reserve()).await {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) => return Err(SendTimeoutError::Closed(ctx)),
+            Err(_) => return Err(SendTimeoutError::Timeout(ctx)),
+        };
+        let sender = self.sending(&ctx);
+        permit.send(ctx);
+        self.sent::<()>(sender, &Ok(()));
+        Ok(())
+    }
+
+    /// Send a cast according to the overflow policy of the mailbox.
+    /// Only fails with Full under `Overflow::Reject`.
+    pub async fn deliver(&self, ctx: Ctx<P, N, E>) -> Result<(), TrySendError<Ctx<P, N, E>>> {
+        match self.overflow {
+            Overflow::Block => self
+                .send(ctx)
+                .await
+                .map_err(|err| TrySendError::Closed(err.0)),
+            _ => self.offer(ctx),
+        }
+    }
+
+    /// blocking version of [`MailboxTx::deliver`]
+    pub fn blocking_deliver(&self, ctx: Ctx<P, N, E>) -> Result<(), TrySendError<Ctx<P, N, E>>> {
+        match self.overflow {
+            Overflow::Block => self
+                .blocking_send(ctx)
+                .map_err(|err| TrySendError::Closed(err.0)),
+            _ => self.offer(ctx),
+        }
+    }
+
+    // try_send, dropping the message on a full queue under Overflow::DropNewest
+    fn offer(&self, ctx: Ctx<P, N, E>) -> Result<(), TrySendError<Ctx<P, N, E>>> {
+        match self.try_send(ctx) {
+            Err(TrySendError::Full(_)) if self.overflow == Overflow::DropNewest => {
+                tracing::warn!(
+                    "mailbox of {} is full, drop the newest message",
+                    self.depth.owner
+                );
+                Ok(())
+            }
+            ret => ret,
+        }
+    }
+
+    /// a call sent by `from` through this mailbox got its reply after `latency`
+    pub(crate) fn record_latency(&self, from: &N, latency: std::time::Duration) {
+        self.stats.record_latency(from, latency)
+    }
+
+    pub(crate) fn stats(&self) -> Arc<MailboxStats<N>> {
+        self.stats.clone()
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match &self.tx {
+            Sender::Bounded(tx) => tx.is_closed(),
+            Sender::Unbounded(tx) => tx.is_closed(),
+        }
+    }
+
+    /// number of messages waiting in both lanes, including senders waiting for capacity
+    pub fn len(&self) -> usize {
+        self.depth.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Receiving side of a component's channel.
+///
+/// Messages of the system lane are always received before those of the normal lane,
+/// each lane in the order it was sent. When the mailbox drops, the underlying receiver is
+/// handed back to the game, so a restarted component keeps the channel every other
+/// component's broker sends to.
+#[derive(Debug)]
+pub struct Mailbox<P, N, E> {
+    rx: Option<Lanes<Ctx<P, N, E>>>,
+    depth: Arc<Depth>,
+    stats: Arc<MailboxStats<N>>,
+    drop_expired: bool,
+    slot: MailboxSlot<P, N, E>,
+}
+
+/// create a mailbox of the component `owner`
+pub(crate) fn channel<P, N, E>(
+    config: &MailboxConfig,
+    owner: String,
+) -> (MailboxTx<P, N, E>, Mailbox<P, N, E>)
+where
+    N: Hash + Eq + Clone,
+{
+    let (tx, rx) = match config.capacity {
+        Some(capacity) => {
+            let (tx, rx) = mpsc::channel(capacity);
+            (Sender::Bounded(tx), Receiver::Bounded(rx))
+        }
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (Sender::Unbounded(tx), Receiver::Unbounded(rx))
+        }
+    };
+    let (system, system_rx) = mpsc::unbounded_channel();
+    let depth = Arc::new(Depth {
+        owner,
+        len: AtomicUsize::new(0),
+        peak: AtomicUsize::new(0),
+        high_water_mark: config.high_water_mark,
+        warned: AtomicBool::new(false),
+    });
+    let rx = Lanes {
+        system: system_rx,
+        normal: rx,
+        depth: depth.clone(),
+    };
+    let stats = Arc::new(MailboxStats::new(depth.clone()));
+    (
+        MailboxTx {
+            tx,
+            system,
+            depth: depth.clone(),
+            stats: stats.clone(),
+            overflow: config.overflow,
+        },
+        Mailbox {
+            rx: Some(rx),
+            depth: depth.clone(),
+            stats: stats.clone(),
+            drop_expired: config.drop_expired,
+            slot: MailboxSlot {
+                rx: Arc::new(Mutex::new(None)),
+                depth,
+                stats,
+                drop_expired: config.drop_expired,
+            },
+        },
+    )
+}
+
+impl<P, N, E> Mailbox<P, N, E> {
+    /// slot where the receiver is returned once the mailbox drops
+    pub(crate) fn slot(&self) -> MailboxSlot<P, N, E> {
+        self.slot.clone()
+    }
+
+    fn rx(&mut self) -> &mut Lanes<Ctx<P, N, E>> {
+        self.rx.as_mut().expect("mailbox receiver already returned")
+    }
+
+    // None if the mailbox drops the expired message
+    fn received(&self, ctx: Ctx<P, N, E>) -> Option<Ctx<P, N, E>> {
+        self.depth.dec();
+        if !self.drop_expired || !ctx.is_expired() {
+            return Some(ctx);
+        }
+        tracing::debug!(
+            "mailbox of {} dropped an expired {}",
+            self.depth.owner,
+            if ctx.is_call() { "call" } else { "cast" }
+        );
+        self.stats.record_expired();
+        ctx.expire();
+        None
+    }
+
+    pub async fn recv(&mut self) -> Option<Ctx<P, N, E>> {
+        loop {
+            let lanes = self.rx();
+            let ctx = tokio::select! {
+                biased;
+                // both lanes close together, the normal lane reports it
+                Some(ctx) = lanes.system.recv() => ctx,
+                ctx = lanes.normal.recv() => ctx?,
+            };
+            if let Some(ctx) = self.received(ctx) {
+                return Some(ctx);
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Ctx<P, N, E>, TryRecvError> {
+        loop {
+            let lanes = self.rx();
+            let ctx = match lanes.system.try_recv() {
+                Ok(ctx) => ctx,
+                Err(_) => lanes.normal.try_recv()?,
+            };
+            if let Some(ctx) = self.received(ctx) {
+                return Ok(ctx);
+            }
+        }
+    }
+
+    /// blocking version of [`Mailbox::recv`], panics inside an async context
+    pub fn blocking_recv(&mut self) -> Option<Ctx<P, N, E>> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            panic!("Cannot block the current thread from within a runtime.");
+        }
+        futures::executor::block_on(self.recv())
+    }
+
+    /// number of messages waiting in the mailbox
+    pub fn len(&self) -> usize {
+        self.depth.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<P, N, E> Drop for Mailbox<P, N, E> {
+    fn drop(&mut self) {
+        if let Some(rx) = self.rx.take() {
+            *self.slot.rx.lock().unwrap_or_else(PoisonError::into_inner) = Some(rx);
+        }
+    }
+}
+
+type Slot<T> = Arc<Mutex<Option<Lanes<T>>>>;
+
+#[derive(Debug)]
+pub(crate) struct MailboxSlot<P, N, E> {
+    rx: Slot<Ctx<P, N, E>>,
+    depth: Arc<Depth>,
+    stats: Arc<MailboxStats<N>>,
+    drop_expired: bool,
+}
+
+impl<P, N, E> Clone for MailboxSlot<P, N, E> {
+    fn clone(&self) -> Self {
+        Self {
+            rx: self.rx.clone(),
+            depth: self.depth.clone(),
+            stats: self.stats.clone(),
+            drop_expired: self.drop_expired,
+        }
+    }
+}
+
+impl<P, N, E> MailboxSlot<P, N, E> {
+    /// reopen the returned receiver as a new mailbox. None if the last mailbox is still alive
+    pub(crate) fn reclaim(&self) -> Option<Mailbox<P, N, E>> {
+        let rx = self
+            .rx
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()?;
+        Some(Mailbox {
+            rx: Some(rx),
+            depth: self.depth.clone(),
+            stats: self.stats.clone(),
+            drop_expired: self.drop_expired,
+            slot: self.clone(),
+        })
+    }
+
+    /// drop the returned receiver once the component stopped for good,
+    /// so senders observe a closed channel instead of queueing forever
+    pub(crate) fn close(&self) {
+        self.rx
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{channel, MailboxConfig, Overflow};
+    use crate::chanrpc::{
+        test_util::{N, P},
+        CallError, ChanCtx, Router,
+    };
+    use futures::StreamExt;
+    use std::{sync::atomic::Ordering, time::Duration};
+    use tokio::{
+        sync::mpsc::error::{SendTimeoutError, TrySendError},
+        time::Instant,
+    };
+
+    #[tokio::test]
+    async fn track_depth() {
+        for config in [MailboxConfig::bounded(4), MailboxConfig::unbounded()] {
+            let (tx, mut rx) = channel::<P, (), ()>(&config, "test".to_string());
+            for _ in 0..3 {
+                tx.send(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+            }
+            assert_eq!(tx.len(), 3);
+            rx.recv().await.unwrap();
+            rx.try_recv().unwrap();
+            assert_eq!(rx.len(), 1);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overflow_policy() {
+        let config = MailboxConfig::bounded(1);
+        let (tx, _rx) = channel::<P, (), ()>(&config, "test".to_string());
+        tx.deliver(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(1);
+        assert!(matches!(
+            tx.send_deadline(ChanCtx::new_cast(P::Tick, ()), deadline)
+                .await,
+            Err(SendTimeoutError::Timeout(_))
+        ));
+
+        let (tx, _rx) = channel::<P, (), ()>(
+            &config.clone().overflow(Overflow::DropNewest),
+            "test".to_string(),
+        );
+        for _ in 0..3 {
+            tx.deliver(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+        }
+        assert_eq!(tx.len(), 1);
+
+        let (tx, _rx) =
+            channel::<P, (), ()>(&config.overflow(Overflow::Reject), "test".to_string());
+        tx.deliver(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+        assert!(matches!(
+            tx.deliver(ChanCtx::new_cast(P::Tick, ())).await,
+            Err(TrySendError::Full(_))
+        ));
+        assert_eq!(tx.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn system_lane_first() {
+        let (tx, mut rx) = channel::<P, u32, ()>(&MailboxConfig::bounded(2), "test".to_string());
+        tx.send(ChanCtx::new_cast(P::Tick, 1)).await.unwrap();
+        tx.send(ChanCtx::new_cast(P::Tick, 2)).await.unwrap();
+        tx.send_system(ChanCtx::new_cast(P::Tick, 3)).unwrap();
+        tx.send_system(ChanCtx::new_cast(P::Tick, 4)).unwrap();
+        assert_eq!(tx.len(), 4);
+        assert_eq!(*rx.recv().await.unwrap().from(), 3);
+        assert_eq!(*rx.try_recv().unwrap().from(), 4);
+        assert_eq!(*rx.recv().await.unwrap().from(), 1);
+        tx.send_system(ChanCtx::new_cast(P::Tick, 5)).unwrap();
+        assert_eq!(*rx.recv().await.unwrap().from(), 5);
+        assert_eq!(*rx.recv().await.unwrap().from(), 2);
+        assert!(rx.is_empty());
+
+        drop(tx);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_expired() {
+        let config = MailboxConfig::default().drop_expired(true);
+        let (tx, mut rx) = channel::<P, N, ()>(&config, "test".to_string());
+        let router = Router::default();
+        router.insert(N::Test, tx.clone()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        tx.send(ChanCtx::new_cast(P::Tick, N::Gate).with_deadline(deadline))
+            .await
+            .unwrap();
+        let (call, reply) = ChanCtx::new_call(P::Tick, N::Player);
+        tx.send(call.with_deadline(deadline)).await.unwrap();
+        let (call, stream) = ChanCtx::new_stream(P::Tick, N::Quest, 1);
+        tx.send(call.with_deadline(deadline)).await.unwrap();
+        tx.send(ChanCtx::new_cast(P::Tick, N::Db)).await.unwrap();
+        let late = Instant::now() + Duration::from_secs(5);
+        tx.send(ChanCtx::new_cast(P::Tick, N::Mail).with_deadline(late))
+            .await
+            .unwrap();
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let ctx = rx.recv().await.unwrap();
+        assert_eq!(*ctx.from(), N::Db);
+        assert!(!ctx.is_expired());
+        assert!(matches!(reply.await, Ok(Err(CallError::Expired))));
+        let items: Vec<_> = stream.collect().await;
+        assert!(matches!(items[..], [Err(CallError::Expired)]));
+        assert_eq!(*rx.try_recv().unwrap().from(), N::Mail);
+        assert!(rx.is_empty());
+        let snapshot = router.metrics_snapshot();
+        assert_eq!(snapshot.mailbox(&N::Test).unwrap().expired, 3);
+    }
+
+    #[tokio::test]
+    async fn bounded_try_send_full() {
+        let (tx, _rx) = channel::<P, (), ()>(&MailboxConfig::bounded(1), "test".to_string());
+        tx.try_send(ChanCtx::new_cast(P::Tick, ())).unwrap();
+        assert!(tx.try_send(ChanCtx::new_cast(P::Tick, ())).is_err());
+        assert_eq!(tx.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn high_water_mark_rearms() {
+        let config = MailboxConfig::bounded(4).high_water_mark(1);
+        let (tx, mut rx) = channel::<P, (), ()>(&config, "test".to_string());
+        for _ in 0..2 {
+            tx.send(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+            assert!(tx.depth.warned.load(Ordering::Relaxed));
+            rx.recv().await.unwrap();
+            assert!(!tx.depth.warned.load(Ordering::Relaxed));
+        }
+    }
+
+    #[test]
+    fn bounded_zero_holds_one() {
+        let (tx, _rx) = channel::<P, (), ()>(&MailboxConfig::bounded(0), "test".to_string());
+        tx.try_send(ChanCtx::new_cast(P::Tick, ())).unwrap();
+        assert!(matches!(
+            tx.try_send(ChanCtx::new_cast(P::Tick, ())),
+            Err(TrySendError::Full(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn closing_drops_queued_from_depth() {
+        let (tx, rx) = channel::<P, (), ()>(&MailboxConfig::bounded(4), "test".to_string());
+        tx.send_system(ChanCtx::new_cast(P::Shutdown, ())).unwrap();
+        for _ in 0..2 {
+            tx.send(ChanCtx::new_cast(P::Tick, ())).await.unwrap();
+        }
+        let slot = rx.slot();
+        // a restarted component receives them later
+        drop(rx);
+        assert_eq!(tx.len(), 3);
+        slot.close();
+        assert_eq!(tx.len(), 0);
+        assert!(tx.send(ChanCtx::new_cast(P::Tick, ())).await.is_err());
+        assert_eq!(tx.len(), 0);
+    }
+}
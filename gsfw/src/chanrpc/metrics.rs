@@ -0,0 +1,312 @@
+use super::mailbox::Depth;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+// upper bounds of the call latency buckets, the last bucket is unbounded
+const LATENCY_BOUNDS: [Duration; 15] = [
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_micros(2500),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+];
+
+/// Call round-trip latencies in fixed buckets from 100µs to 5s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; LATENCY_BOUNDS.len() + 1],
+    sum: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; LATENCY_BOUNDS.len() + 1],
+            sum: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BOUNDS.partition_point(|bound| *bound < latency);
+        self.counts[bucket] += 1;
+        self.sum += latency;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        // in nanoseconds, the count may not fit in the u32 Duration divides by
+        let nanos = self.sum.as_nanos().checked_div(self.count() as u128)?;
+        Some(Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        ))
+    }
+
+    /// count of each bucket by upper bound, None is the unbounded last bucket
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        LATENCY_BOUNDS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain(Some(None))
+            .zip(self.counts.iter().copied())
+    }
+
+    /// upper bound of the bucket holding the `q` quantile, `q` within 0..=1
+    pub fn quantile(&self, q: f64) -> Option<Option<Duration>> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets().find_map(|(bound, n)| {
+            seen += n;
+            (seen >= rank).then_some(bound)
+        })
+    }
+}
+
+/// Traffic from one sender to one mailbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairMetrics<N> {
+    pub from: N,
+    pub casts: u64,
+    pub calls: u64,
+    pub call_latency: Histogram,
+}
+
+impl<N> PairMetrics<N> {
+    pub fn messages(&self) -> u64 {
+        self.casts + self.calls
+    }
+}
+
+/// Queue depth and traffic of the mailbox of the component `name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailboxMetrics<N> {
+    pub name: N,
+    /// messages currently queued, including senders waiting for capacity
+    pub depth: usize,
+    /// highest depth since the mailbox was created
+    pub peak_depth: usize,
+    /// messages dropped unhandled because their deadline passed
+    pub expired: u64,
+    /// one entry per sender, in no particular order
+    pub senders: Vec<PairMetrics<N>>,
+}
+
+/// Metrics of every routed mailbox, see [`crate::gs::Game::metrics_snapshot`]
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot<N> {
+    pub taken_at: Instant,
+    pub mailboxes: Vec<MailboxMetrics<N>>,
+}
+
+impl<N: Eq + Clone> MetricsSnapshot<N> {
+    pub fn mailbox(&self, name: &N) -> Option<&MailboxMetrics<N>> {
+        self.mailboxes.iter().find(|mailbox| mailbox.name == *name)
+    }
+
+    /// Messages per second of every (sender, receiver) pair between `earlier` and this
+    /// snapshot. Pairs without traffic in between are left out.
+    pub fn rates(&self, earlier: &Self) -> Vec<(N, N, f64)> {
+        let secs = self.taken_at.duration_since(earlier.taken_at).as_secs_f64();
+        if secs == 0.0 {
+            return Vec::new();
+        }
+        let mut rates = Vec::new();
+        for mailbox in &self.mailboxes {
+            let before = earlier.mailbox(&mailbox.name);
+            for pair in &mailbox.senders {
+                let sent_before = before
+                    .and_then(|m| m.senders.iter().find(|p| p.from == pair.from))
+                    .map_or(0, PairMetrics::messages);
+                // a respawned mailbox starts counting from zero again
+                let sent = pair
+                    .messages()
+                    .checked_sub(sent_before)
+                    .unwrap_or(pair.messages());
+                if sent > 0 {
+                    rates.push((pair.from.clone(), mailbox.name.clone(), sent as f64 / secs));
+                }
+            }
+        }
+        rates
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pair {
+    casts: u64,
+    calls: u64,
+    call_latency: Histogram,
+}
+
+/// Counters of one mailbox, shared by its senders and the metrics registry
+#[derive(Debug)]
+pub(crate) struct MailboxStats<N> {
+    depth: Arc<Depth>,
+    expired: AtomicU64,
+    senders: Mutex<HashMap<N, Pair>>,
+}
+
+impl<N> MailboxStats<N> {
+    /// the mailbox dropped a message past its deadline
+    pub(crate) fn record_expired(&self) {
+        self.expired.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<N: Hash + Eq + Clone> MailboxStats<N> {
+    pub(crate) fn new(depth: Arc<Depth>) -> Self {
+        Self {
+            depth,
+            expired: AtomicU64::new(0),
+            senders: Default::default(),
+        }
+    }
+
+    fn with_pair(&self, from: &N, f: impl FnOnce(&mut Pair)) {
+        let mut senders = self.senders.lock().unwrap_or_else(PoisonError::into_inner);
+        match senders.get_mut(from) {
+            Some(pair) => f(pair),
+            None => f(senders.entry(from.clone()).or_default()),
+        }
+    }
+
+    /// a message from `from` was queued
+    pub(crate) fn record_message(&self, from: &N, call: bool) {
+        self.with_pair(from, |pair| match call {
+            true => pair.calls += 1,
+            false => pair.casts += 1,
+        })
+    }
+
+    /// a call from `from` got its reply after `latency`
+    pub(crate) fn record_latency(&self, from: &N, latency: Duration) {
+        self.with_pair(from, |pair| pair.call_latency.record(latency))
+    }
+
+    fn snapshot(&self, name: N) -> MailboxMetrics<N> {
+        let senders = self.senders.lock().unwrap_or_else(PoisonError::into_inner);
+        MailboxMetrics {
+            name,
+            depth: self.depth.len(),
+            peak_depth: self.depth.peak(),
+            expired: self.expired.load(Ordering::Relaxed),
+            senders: senders
+                .iter()
+                .map(|(from, pair)| PairMetrics {
+                    from: from.clone(),
+                    casts: pair.casts,
+                    calls: pair.calls,
+                    call_latency: pair.call_latency.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+type Registered<N> = Arc<Mutex<Vec<(N, Arc<MailboxStats<N>>)>>>;
+
+/// Stats of every routed mailbox in routing order, kept in sync by the router
+#[derive(Debug)]
+pub(crate) struct Metrics<N>(Registered<N>);
+
+impl<N> Clone for Metrics<N> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<N> Default for Metrics<N> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<N: Hash + Eq + Clone> Metrics<N> {
+    pub(crate) fn insert(&self, name: N, stats: Arc<MailboxStats<N>>) {
+        let mut registered = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        registered.retain(|(n, _)| *n != name);
+        registered.push((name, stats));
+    }
+
+    pub(crate) fn remove(&self, name: &N) {
+        let mut registered = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        registered.retain(|(n, _)| n != name);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot<N> {
+        let registered = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        MetricsSnapshot {
+            taken_at: Instant::now(),
+            mailboxes: registered
+                .iter()
+                .map(|(name, stats)| stats.snapshot(name.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Histogram;
+    use std::time::Duration;
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        for ms in [1, 1, 3, 40, 9000] {
+            histogram.record(Duration::from_millis(ms));
+        }
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(9045 / 5)));
+        assert_eq!(
+            histogram.quantile(0.5),
+            Some(Some(Duration::from_millis(5)))
+        );
+        assert_eq!(histogram.quantile(1.0), Some(None));
+        let first = histogram.buckets().find(|(_, n)| *n > 0);
+        assert_eq!(first, Some((Some(Duration::from_millis(1)), 2)));
+    }
+
+    #[test]
+    fn mean_of_many_calls() {
+        let mut histogram = Histogram::default();
+        histogram.counts[0] = 1 << 32;
+        histogram.sum = Duration::from_secs(1 << 32);
+        assert_eq!(histogram.mean(), Some(Duration::from_secs(1)));
+        histogram.counts[0] = 3;
+        histogram.sum = Duration::from_secs(1);
+        assert_eq!(histogram.mean(), Some(Duration::from_nanos(333_333_333)));
+    }
+}
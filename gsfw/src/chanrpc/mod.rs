@@ -1,7 +1,25 @@
 mod casttx;
 mod calltx;
 mod ctx;
+mod error;
+pub(crate) mod mailbox;
+mod metrics;
+mod router;
+mod stream;
+#[cfg(test)]
+pub(crate) mod test_util;
+mod trace;
 pub mod broker;
-pub use ctx::{ChanCtx, Proto, Name};
+pub mod pubsub;
+pub mod remote;
+pub use ctx::{ChanCtx, IntoReply, Proto, Name, Request, Responder};
 pub use calltx::CallTx;
-pub use casttx::CastTx;
\ No newline at end of file
+pub use error::{CallError, CastError, StreamClosed};
+pub use casttx::CastTx;
+pub use mailbox::{Mailbox, MailboxConfig, MailboxTx, Overflow};
+pub(crate) use mailbox::MailboxSlot;
+pub use metrics::{Histogram, MailboxMetrics, MetricsSnapshot, PairMetrics};
+pub(crate) use metrics::Metrics;
+pub use router::{CastReport, Router};
+pub use stream::{ReplyStream, StreamTx};
+pub use trace::{CorrelationId, Trace};
\ No newline at end of file
@@ -0,0 +1,161 @@
+use super::{broker::Broker, CastReport, ChanCtx, Router};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+type RouterOf<B> = Router<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>;
+
+struct Inner<T, B: Broker> {
+    router: RouterOf<B>,
+    // subscribers of each topic in subscription order
+    topics: RwLock<HashMap<T, Vec<B::Name>>>,
+}
+
+/// Topic based publish/subscribe on top of the component mailboxes.
+///
+/// Obtained from [`crate::gs::GameBuilder::bus`]. Components subscribe in `init`, published
+/// messages reach the subscribers as regular casts. The subscriptions of a component are
+/// dropped once it stopped for good, they survive a restart since the mailbox does.
+pub struct Bus<T, B: Broker> {
+    inner: Arc<Inner<T, B>>,
+}
+
+impl<T, B: Broker> Clone for Bus<T, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Debug, B: Broker> Debug for Bus<T, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let topics = self
+            .inner
+            .topics
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Bus").field("topics", &*topics).finish()
+    }
+}
+
+impl<T, B> Bus<T, B>
+where
+    T: Hash + Eq + Clone + Debug,
+    B: Broker,
+{
+    pub(crate) fn new(router: RouterOf<B>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                router,
+                topics: Default::default(),
+            }),
+        }
+    }
+
+    /// deliver messages published to `topic` to the component `name`
+    pub fn subscribe(&self, topic: T, name: B::Name) {
+        let mut topics = self
+            .inner
+            .topics
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let subscribers = topics.entry(topic).or_default();
+        if !subscribers.contains(&name) {
+            subscribers.push(name);
+        }
+    }
+
+    pub fn unsubscribe(&self, topic: &T, name: &B::Name) {
+        let mut topics = self
+            .inner
+            .topics
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(subscribers) = topics.get_mut(topic) {
+            subscribers.retain(|n| n != name);
+            if subscribers.is_empty() {
+                topics.remove(topic);
+            }
+        }
+    }
+
+    /// drop every subscription of the component `name`
+    pub(crate) fn unsubscribe_all(&self, name: &B::Name) {
+        let mut topics = self
+            .inner
+            .topics
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        topics.retain(|_, subscribers| {
+            subscribers.retain(|n| n != name);
+            !subscribers.is_empty()
+        });
+    }
+
+    pub fn subscribers(&self, topic: &T) -> Vec<B::Name> {
+        let topics = self
+            .inner
+            .topics
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        topics.get(topic).cloned().unwrap_or_default()
+    }
+
+    /// cast `msg` from `from` to every subscriber of `topic`
+    pub async fn publish(&self, from: B::Name, topic: &T, msg: B::Proto) -> CastReport<B::Name>
+    where
+        B::Proto: Clone,
+    {
+        let targets = self
+            .subscribers(topic)
+            .into_iter()
+            .map(|name| (name, ChanCtx::new_cast(msg.clone(), from.clone())))
+            .collect();
+        self.inner.router.send_each(targets).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Bus;
+    use crate::chanrpc::{
+        mailbox,
+        test_util::{Bk, N, P},
+        MailboxConfig, Router,
+    };
+
+    #[tokio::test]
+    async fn publish_to_subscribers() {
+        let router = Router::default();
+        let config = MailboxConfig::default();
+        let (mail, mut mail_rx) = mailbox::channel(&config, "mail".to_string());
+        let (quest, mut quest_rx) = mailbox::channel(&config, "quest".to_string());
+        router.insert(N::Mail, mail).unwrap();
+        router.insert(N::Quest, quest).unwrap();
+
+        let bus = Bus::<&str, Bk>::new(router);
+        bus.subscribe("daily_reset", N::Mail);
+        bus.subscribe("daily_reset", N::Quest);
+        bus.subscribe("daily_reset", N::Quest);
+        bus.subscribe("maintenance", N::Mail);
+        assert_eq!(bus.subscribers(&"daily_reset"), vec![N::Mail, N::Quest]);
+
+        let report = bus.publish(N::Gm, &"daily_reset", P::Tick).await;
+        assert!(report.is_ok());
+        assert_eq!(report.sent, 2);
+        let ctx = mail_rx.try_recv().unwrap();
+        assert_eq!(*ctx.from(), N::Gm);
+        assert_eq!(*ctx.payload(), P::Tick);
+        assert_eq!(quest_rx.try_recv().unwrap().into_payload(), P::Tick);
+
+        bus.unsubscribe_all(&N::Mail);
+        assert_eq!(bus.subscribers(&"daily_reset"), vec![N::Quest]);
+        assert!(bus.subscribers(&"maintenance").is_empty());
+        let report = bus.publish(N::Gm, &"maintenance", P::Get).await;
+        assert_eq!(report.sent, 0);
+    }
+}
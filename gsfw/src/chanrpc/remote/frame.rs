@@ -0,0 +1,137 @@
+use crate::error::Error;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// What a frame carries, the first byte of every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Cast = 0,
+    Call = 1,
+    // the callee replied Ok, body is the reply proto
+    Reply = 2,
+    // the callee replied Err, body is the encoded error
+    Fail = 3,
+    // the call could not be delivered or the callee dropped it
+    Lost = 4,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = Error;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(Self::Cast),
+            1 => Ok(Self::Call),
+            2 => Ok(Self::Reply),
+            3 => Ok(Self::Fail),
+            4 => Ok(Self::Lost),
+            _ => Err(Error::FrameFormat),
+        }
+    }
+}
+
+/// One message of a remote link. The length prefix is handled by the codec, the frame is
+/// `[kind][request id][correlation id][from len][from][to len][to][body]` and the body of
+/// casts, calls and replies is `[msgid][payload]` as written by `RegistryExt::encode_to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Envelope {
+    pub(crate) kind: Kind,
+    // matches replies to calls, 0 for casts
+    pub(crate) id: u64,
+    pub(crate) correlation_id: u64,
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) body: Bytes,
+}
+
+impl Envelope {
+    /// reply frame of the call `id`
+    pub(crate) fn reply(kind: Kind, id: u64, correlation_id: u64, body: Bytes) -> Self {
+        Self {
+            kind,
+            id,
+            correlation_id,
+            from: String::new(),
+            to: String::new(),
+            body,
+        }
+    }
+
+    /// fails if a name is longer than its u16 length prefix allows
+    pub(crate) fn encode(&self) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::with_capacity(
+            1 + 8 + 8 + 2 + self.from.len() + 2 + self.to.len() + self.body.len(),
+        );
+        buf.put_u8(self.kind as u8);
+        buf.put_u64(self.id);
+        buf.put_u64(self.correlation_id);
+        write_str(&mut buf, &self.from)?;
+        write_str(&mut buf, &self.to)?;
+        buf.put_slice(&self.body);
+        Ok(buf.freeze())
+    }
+
+    pub(crate) fn decode(mut frame: Bytes) -> Result<Self, Error> {
+        if frame.remaining() < 1 + 8 + 8 {
+            return Err(Error::FrameFormat);
+        }
+        let kind = Kind::try_from(frame.get_u8())?;
+        let id = frame.get_u64();
+        let correlation_id = frame.get_u64();
+        let from = read_str(&mut frame)?;
+        let to = read_str(&mut frame)?;
+        Ok(Self {
+            kind,
+            id,
+            correlation_id,
+            from,
+            to,
+            body: frame,
+        })
+    }
+}
+
+fn write_str(buf: &mut BytesMut, name: &str) -> Result<(), Error> {
+    let len = u16::try_from(name.len())
+        .map_err(|_| Error::Encode(format!("name of {} bytes is too long", name.len())))?;
+    buf.put_u16(len);
+    buf.put_slice(name.as_bytes());
+    Ok(())
+}
+
+fn read_str(frame: &mut Bytes) -> Result<String, Error> {
+    if frame.remaining() < 2 {
+        return Err(Error::FrameFormat);
+    }
+    let len = frame.get_u16() as usize;
+    if frame.remaining() < len {
+        return Err(Error::FrameFormat);
+    }
+    String::from_utf8(frame.split_to(len).to_vec()).map_err(|_| Error::FrameFormat)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Envelope, Kind};
+    use bytes::Bytes;
+
+    #[test]
+    fn encode_decode() {
+        let envelope = Envelope {
+            kind: Kind::Call,
+            id: 7,
+            correlation_id: 42,
+            from: "Gate".to_string(),
+            to: "Db".to_string(),
+            body: Bytes::from_static(&[0, 0, 0, 1, 9, 9]),
+        };
+        let frame = envelope.encode().unwrap();
+        assert_eq!(Envelope::decode(frame.clone()).unwrap(), envelope);
+        assert!(Envelope::decode(frame.slice(..20)).is_err());
+
+        let long = Envelope {
+            to: "a".repeat(u16::MAX as usize + 1),
+            ..envelope
+        };
+        assert!(long.encode().is_err());
+    }
+}
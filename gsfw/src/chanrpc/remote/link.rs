@@ -0,0 +1,183 @@
+use super::{
+    codec, encode_proto,
+    frame::{Envelope, Kind},
+    RemoteErr, RECONNECT_MAX, RECONNECT_MIN,
+};
+use crate::{
+    chanrpc::{ChanCtx, Mailbox, Name, Proto},
+    gs::ShutdownHandle,
+    registry::RegistryExt,
+};
+use futures::{stream, SinkExt, Stream, StreamExt};
+use std::{collections::HashMap, fmt::Display};
+use tokio::net::TcpStream;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+// why a connected link stopped
+enum Exit {
+    // the mailbox closed or the game is shutting down
+    Done,
+    Disconnected,
+}
+
+/// Forward every message queued in the mailboxes of the remote names to the game listening
+/// on `addr`, reconnecting until the game shuts down
+pub(crate) async fn link<P, N, E>(
+    addr: String,
+    mailboxes: Vec<(N, Mailbox<P, N, E>)>,
+    shutdown: ShutdownHandle,
+) where
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    let slots: Vec<_> = mailboxes
+        .iter()
+        .map(|(_, mailbox)| mailbox.slot())
+        .collect();
+    // messages of every remote name, tagged with the name they were sent to
+    let mut queued = stream::select_all(mailboxes.into_iter().map(|(name, mailbox)| {
+        Box::pin(stream::unfold(
+            (name, mailbox),
+            |(name, mut mailbox)| async move {
+                let ctx = mailbox.recv().await?;
+                Some(((name.clone(), ctx), (name, mailbox)))
+            },
+        ))
+    }));
+    let mut backoff = RECONNECT_MIN;
+    loop {
+        let stream = tokio::select! {
+            _ = shutdown.wait() => break,
+            stream = TcpStream::connect(addr.as_str()) => stream,
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!(
+                    "fail to connect remote {}. {}, retry in {:?}",
+                    addr,
+                    err,
+                    backoff
+                );
+                tokio::select! {
+                    _ = shutdown.wait() => break,
+                    _ = tokio::time::sleep(backoff) => (),
+                }
+                backoff = (backoff * 2).min(RECONNECT_MAX);
+                continue;
+            }
+        };
+        tracing::info!("remote link to {} connected", addr);
+        backoff = RECONNECT_MIN;
+        match forward(Framed::new(stream, codec()), &mut queued, &shutdown).await {
+            Exit::Done => break,
+            Exit::Disconnected => tracing::warn!("remote link to {} disconnected", addr),
+        }
+    }
+    // senders observe closed mailboxes from now on
+    drop(queued);
+    slots.iter().for_each(|slot| slot.close());
+}
+
+async fn forward<P, N, E, S>(
+    mut framed: Framed<TcpStream, LengthDelimitedCodec>,
+    queued: &mut S,
+    shutdown: &ShutdownHandle,
+) -> Exit
+where
+    P: Proto + RegistryExt,
+    N: Name + Display,
+    E: RemoteErr,
+    S: Stream<Item = (N, ChanCtx<P, N, E>)> + Unpin,
+{
+    // calls waiting for their reply, dropped on disconnect so callers get NoReply
+    let mut pending: HashMap<u64, ChanCtx<P, N, E>> = HashMap::new();
+    let mut next_id = 0;
+    loop {
+        tokio::select! {
+            _ = shutdown.wait() => return Exit::Done,
+            queued = queued.next() => {
+                let (to, ctx) = match queued {
+                    Some(queued) => queued,
+                    None => return Exit::Done,
+                };
+                let body = match encode_proto(ctx.payload()) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        tracing::error!("fail to encode remote message. {}", err);
+                        continue;
+                    }
+                };
+                let (kind, id) = match ctx.is_call() {
+                    true => {
+                        next_id += 1;
+                        (Kind::Call, next_id)
+                    }
+                    false => (Kind::Cast, 0),
+                };
+                let envelope = Envelope {
+                    kind,
+                    id,
+                    correlation_id: ctx.trace().correlation_id().get(),
+                    from: ctx.from().to_string(),
+                    to: to.to_string(),
+                    body,
+                };
+                // a call that cannot be encoded is dropped, its caller gets NoReply
+                let frame = match envelope.encode() {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        tracing::error!("fail to encode remote message to {}. {}", to, err);
+                        continue;
+                    }
+                };
+                if kind == Kind::Call {
+                    pending.insert(id, ctx);
+                }
+                if let Err(err) = framed.send(frame).await {
+                    tracing::error!("fail to send to remote. {}", err);
+                    return Exit::Disconnected;
+                }
+            }
+            frame = framed.next() => {
+                let envelope = match frame {
+                    Some(Ok(frame)) => Envelope::decode(frame.freeze()),
+                    Some(Err(err)) => {
+                        tracing::error!("fail to read from remote. {}", err);
+                        return Exit::Disconnected;
+                    }
+                    None => return Exit::Disconnected,
+                };
+                match envelope {
+                    Ok(envelope) => reply(&mut pending, envelope),
+                    Err(err) => tracing::error!("invalid frame from remote. {}", err),
+                }
+            }
+        }
+    }
+}
+
+// complete the call a reply frame answers
+fn reply<P, N, E>(pending: &mut HashMap<u64, ChanCtx<P, N, E>>, envelope: Envelope)
+where
+    P: Proto + RegistryExt,
+    E: RemoteErr,
+{
+    let ctx = match pending.remove(&envelope.id) {
+        Some(ctx) => ctx,
+        None => return tracing::warn!("remote reply to unknown call {}", envelope.id),
+    };
+    match envelope.kind {
+        Kind::Reply => match P::decode_frame(envelope.body) {
+            Ok(reply) => ctx.ok(reply),
+            Err(err) => tracing::error!("fail to decode remote reply. {}", err),
+        },
+        Kind::Fail => match E::decode(envelope.body) {
+            Ok(err) => ctx.err(err),
+            Err(err) => tracing::error!("fail to decode remote error. {}", err),
+        },
+        Kind::Lost => tracing::debug!("remote call {} lost", envelope.id),
+        kind => tracing::error!("unexpected {:?} frame from remote", kind),
+    }
+}
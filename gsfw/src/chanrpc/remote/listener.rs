@@ -0,0 +1,371 @@
+use super::{
+    codec, encode_err, encode_proto,
+    frame::{Envelope, Kind},
+    RemoteErr,
+};
+use crate::{
+    chanrpc::{CallError, ChanCtx, CorrelationId, MailboxTx, Name, Proto, Router, Trace},
+    error::Error,
+    gs::ShutdownHandle,
+    registry::RegistryExt,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::{collections::HashSet, fmt::Display, net::SocketAddr, str::FromStr, sync::Arc};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{mpsc, Semaphore},
+};
+use tokio_util::codec::Framed;
+use tracing::Instrument;
+
+// casts of one connection waiting for room in their mailbox, the connection stops reading
+// from the peer while the queue is full
+const CAST_QUEUE: usize = 256;
+// calls of one connection delivered and not answered yet, calls beyond are answered Lost
+const CALLS_IN_FLIGHT: usize = 256;
+
+// a remote cast on its way to the mailbox of its target
+type Cast<P, N, E> = (N, MailboxTx<P, N, E>, ChanCtx<P, N, E>);
+
+/// Accept remote links on `listener` and deliver their messages to the `exports` among the
+/// routed components until the game shuts down
+pub(crate) async fn listen<P, N, E>(
+    listener: TcpListener,
+    exports: Arc<HashSet<N>>,
+    router: Router<P, N, E>,
+    shutdown: ShutdownHandle,
+) where
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + FromStr + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    loop {
+        let accepted = tokio::select! {
+            _ = shutdown.wait() => return,
+            accepted = listener.accept() => accepted,
+        };
+        match accepted {
+            Ok((stream, remote)) => {
+                tracing::info!("remote link from {} accepted", remote);
+                let conn = serve(
+                    stream,
+                    remote,
+                    exports.clone(),
+                    router.clone(),
+                    shutdown.clone(),
+                );
+                tokio::spawn(conn.instrument(tracing::debug_span!("remote", %remote).or_current()));
+            }
+            Err(err) => tracing::error!("fail to accept remote link. {}", err),
+        }
+    }
+}
+
+async fn serve<S, P, N, E>(
+    stream: S,
+    remote: SocketAddr,
+    exports: Arc<HashSet<N>>,
+    router: Router<P, N, E>,
+    shutdown: ShutdownHandle,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + FromStr + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    let mut framed = Framed::new(stream, codec());
+    // replies of the calls in flight, written by the connection task. at most one per call
+    // or frame read, so the queue is bounded by the limits below
+    let (replies, mut replies_rx) = mpsc::unbounded_channel::<Envelope>();
+    // one task forwards the casts in the order they were read
+    let (casts, casts_rx) = mpsc::channel(CAST_QUEUE);
+    tokio::spawn(forward_casts(casts_rx));
+    let calls = Arc::new(Semaphore::new(CALLS_IN_FLIGHT));
+    loop {
+        tokio::select! {
+            _ = shutdown.wait() => return,
+            Some(reply) = replies_rx.recv() => {
+                // replies carry no names
+                let frame = match reply.encode() {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        tracing::error!("fail to encode reply to remote {}. {}", remote, err);
+                        continue;
+                    }
+                };
+                if let Err(err) = framed.send(frame).await {
+                    return tracing::error!("fail to reply to remote {}. {}", remote, err);
+                }
+            }
+            frame = framed.next() => {
+                let frame = match frame {
+                    Some(Ok(frame)) => frame.freeze(),
+                    Some(Err(err)) => return tracing::error!("fail to read from remote {}. {}", remote, err),
+                    None => return tracing::info!("remote link from {} closed", remote),
+                };
+                let envelope = match Envelope::decode(frame) {
+                    Ok(envelope) => envelope,
+                    Err(err) => {
+                        tracing::error!("invalid frame from remote {}. {}", remote, err);
+                        continue;
+                    }
+                };
+                if let Some(cast) = deliver(&exports, &router, envelope, &replies, &calls) {
+                    // stop reading from the peer while the casts queue is full
+                    tokio::select! {
+                        _ = shutdown.wait() => return,
+                        _ = casts.send(cast) => (),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Hand a call to the target component off the connection task, or return the cast to
+// queue. Calls that cannot be delivered, are refused or exceed the in flight limit are
+// answered with Lost, so the caller does not wait for a reply that never comes
+fn deliver<P, N, E>(
+    exports: &HashSet<N>,
+    router: &Router<P, N, E>,
+    envelope: Envelope,
+    replies: &mpsc::UnboundedSender<Envelope>,
+    calls: &Arc<Semaphore>,
+) -> Option<Cast<P, N, E>>
+where
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + FromStr + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    let (id, correlation_id) = (envelope.id, envelope.correlation_id);
+    let lost = || {
+        if envelope.kind == Kind::Call {
+            let _ = replies.send(Envelope::reply(
+                Kind::Lost,
+                id,
+                correlation_id,
+                Bytes::new(),
+            ));
+        }
+    };
+    let (from, to, msg) = match decode(&envelope) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            tracing::error!("fail to decode remote {:?}. {}", envelope.kind, err);
+            lost();
+            return None;
+        }
+    };
+    if !exports.contains(&to) {
+        tracing::warn!(
+            "remote {:?} to {} refused, it is not exported",
+            envelope.kind,
+            to
+        );
+        lost();
+        return None;
+    }
+    // only the game itself shuts its components down
+    if RegistryExt::msgid(&msg) == RegistryExt::msgid(&P::proto_shutdown()) {
+        tracing::warn!("remote shutdown of {} refused", to);
+        lost();
+        return None;
+    }
+    let tx = match router.get(&to) {
+        Ok(tx) => tx,
+        Err(err) => {
+            tracing::warn!("remote {:?} dropped. {}", envelope.kind, err);
+            lost();
+            return None;
+        }
+    };
+    // messages sent while handling keep the correlation id of the remote request
+    let trace = Trace::new(CorrelationId::from(correlation_id), None);
+    match envelope.kind {
+        Kind::Cast => {
+            let ctx = trace.sync_scope(|| ChanCtx::new_cast(msg, from));
+            Some((to, tx, ctx))
+        }
+        Kind::Call => {
+            let permit = match calls.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    tracing::warn!("too many remote calls in flight, call to {} lost", to);
+                    lost();
+                    return None;
+                }
+            };
+            let (ctx, reply) = trace.sync_scope(|| ChanCtx::new_call(msg, from));
+            let replies = replies.clone();
+            // wait for room and for the reply off the connection task
+            tokio::spawn(async move {
+                let _permit = permit;
+                if tx.send(ctx).await.is_err() {
+                    let _ = replies.send(Envelope::reply(
+                        Kind::Lost,
+                        id,
+                        correlation_id,
+                        Bytes::new(),
+                    ));
+                    return;
+                }
+                let (kind, body) = match reply.await {
+                    Ok(Ok(reply)) => match encode_proto(&reply) {
+                        Ok(body) => (Kind::Reply, body),
+                        Err(err) => {
+                            tracing::error!("fail to encode remote reply. {}", err);
+                            (Kind::Lost, Bytes::new())
+                        }
+                    },
+                    Ok(Err(CallError::Callee(err))) => (Kind::Fail, encode_err(&err)),
+                    _ => (Kind::Lost, Bytes::new()),
+                };
+                let _ = replies.send(Envelope::reply(kind, id, correlation_id, body));
+            });
+            None
+        }
+        kind => {
+            tracing::error!("unexpected {:?} frame from remote", kind);
+            None
+        }
+    }
+}
+
+// deliver the casts of a connection one after the other, following the overflow policy
+// of each target. ends once the connection closed and the queue drained
+async fn forward_casts<P, N, E>(mut casts: mpsc::Receiver<Cast<P, N, E>>)
+where
+    N: Name + Display,
+{
+    while let Some((to, tx, ctx)) = casts.recv().await {
+        if let Err(err) = tx.deliver(ctx).await {
+            tracing::warn!("remote cast to {} dropped. {}", to, err);
+        }
+    }
+}
+
+fn decode<P, N>(envelope: &Envelope) -> Result<(N, N, P), Error>
+where
+    P: RegistryExt,
+    N: FromStr,
+{
+    let name = |name: &str| {
+        N::from_str(name).map_err(|_| Error::Decode(format!("unknown component name {}", name)))
+    };
+    Ok((
+        name(&envelope.from)?,
+        name(&envelope.to)?,
+        P::decode_frame(envelope.body.clone())?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{serve, CALLS_IN_FLIGHT};
+    use crate::{
+        chanrpc::{
+            mailbox,
+            remote::{
+                codec, encode_proto,
+                frame::{Envelope, Kind},
+            },
+            test_util::{N, P},
+            Mailbox, MailboxConfig, Router,
+        },
+        gs::ShutdownHandle,
+    };
+    use futures::{SinkExt, StreamExt};
+    use std::{collections::HashSet, sync::Arc};
+    use tokio::io::DuplexStream;
+    use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+    // a connection exporting Db, routed to a mailbox of one slot nobody reads yet, along
+    // with the unexported Mail
+    fn connect() -> (
+        Framed<DuplexStream, LengthDelimitedCodec>,
+        Mailbox<P, N, String>,
+        ShutdownHandle,
+    ) {
+        let router = Router::<P, N, String>::default();
+        let (tx, rx) = mailbox::channel(&MailboxConfig::bounded(1), "Db".to_string());
+        router.insert(N::Db, tx).unwrap();
+        let (mail, _) = mailbox::channel(&MailboxConfig::default(), "Mail".to_string());
+        router.insert(N::Mail, mail).unwrap();
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let shutdown = ShutdownHandle::new();
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let exports = Arc::new(HashSet::from([N::Db]));
+        tokio::spawn(serve(server, addr, exports, router, shutdown.clone()));
+        (Framed::new(client, codec()), rx, shutdown)
+    }
+
+    fn frame(kind: Kind, id: u64, msg: P) -> Envelope {
+        Envelope {
+            kind,
+            id,
+            correlation_id: 0,
+            from: "Gate".to_string(),
+            to: N::Db.to_string(),
+            body: encode_proto(&msg).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn casts_keep_order() {
+        let (mut peer, mut rx, shutdown) = connect();
+        for n in 0..10 {
+            let cast = frame(Kind::Cast, 0, P::Count(n));
+            peer.send(cast.encode().unwrap()).await.unwrap();
+        }
+        for n in 0..10 {
+            assert_eq!(rx.recv().await.unwrap().payload(), &P::Count(n));
+        }
+        shutdown.shutdown();
+    }
+
+    #[tokio::test]
+    async fn calls_in_flight_are_capped() {
+        let (mut peer, mut rx, shutdown) = connect();
+        let over = CALLS_IN_FLIGHT as u64 + 1;
+        for id in 1..=over {
+            let call = frame(Kind::Call, id, P::Ping(id as u32));
+            peer.send(call.encode().unwrap()).await.unwrap();
+        }
+        let reply = Envelope::decode(peer.next().await.unwrap().unwrap().freeze()).unwrap();
+        assert_eq!((reply.kind, reply.id), (Kind::Lost, over));
+
+        // the calls within the limit are delivered
+        let (payload, responder) = rx.recv().await.unwrap().into_parts();
+        assert_eq!(payload, P::Ping(1));
+        responder.ok(P::Pong(1));
+        let reply = Envelope::decode(peer.next().await.unwrap().unwrap().freeze()).unwrap();
+        assert_eq!((reply.kind, reply.id), (Kind::Reply, 1));
+        shutdown.shutdown();
+    }
+
+    #[tokio::test]
+    async fn refuse_unexported_and_shutdown() {
+        let (mut peer, mut rx, shutdown) = connect();
+        let to_mail = Envelope {
+            to: N::Mail.to_string(),
+            ..frame(Kind::Call, 1, P::Get)
+        };
+        let calls = [to_mail, frame(Kind::Call, 2, P::Shutdown)];
+        for (id, call) in (1..).zip(calls) {
+            peer.send(call.encode().unwrap()).await.unwrap();
+            let reply = Envelope::decode(peer.next().await.unwrap().unwrap().freeze()).unwrap();
+            assert_eq!((reply.kind, reply.id), (Kind::Lost, id));
+        }
+
+        for cast in [
+            frame(Kind::Cast, 0, P::Shutdown),
+            frame(Kind::Cast, 0, P::Tick),
+        ] {
+            peer.send(cast.encode().unwrap()).await.unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap().payload(), &P::Tick);
+        shutdown.shutdown();
+    }
+}
@@ -0,0 +1,319 @@
+//! Broker transport between gsfw processes over TCP.
+//!
+//! A game lists the names hosted by another process with
+//! [`crate::gs::GameBuilder::remote_link`]. Those names are routed to a local mailbox that a
+//! link task forwards to the peer, so `cast` and `call` reach them like any local component.
+//! The peer accepts links with [`crate::gs::GameBuilder::remote_listen`] and delivers the
+//! messages to its own components, call replies travel back on the same connection and are
+//! matched by request id.
+//!
+//! Links are not authenticated, any peer that connects to the listener can message the
+//! components it exports, so only listen on a trusted network. Messages to names that are not
+//! exported and remote shutdown messages are refused, calls among them fail with
+//! [`super::CallError::NoReply`].
+//!
+//! Frames use the length prefixed layout of [`crate::registry::RegistryExt::encode_with_len`],
+//! names are sent as their `Display` text and parsed back with `FromStr`. The link reconnects
+//! with a backoff when the connection drops. Messages are delivered at most once, calls in
+//! flight when a connection drops fail with [`super::CallError::NoReply`]. Streaming calls
+//! travel as plain calls, the remote callee answers them with a single item.
+use super::{mailbox, MailboxConfig, Name, Proto, Router};
+use crate::{error::Error, gs::ShutdownHandle, registry::RegistryExt};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::{fmt::Display, str::FromStr, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+use tokio_util::codec::LengthDelimitedCodec;
+
+mod frame;
+mod link;
+mod listener;
+
+// delay before the first reconnect, doubled up to the max on every failed attempt
+const RECONNECT_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_MAX: Duration = Duration::from_secs(5);
+
+/// Wire format of the error a remote callee replies with
+pub trait RemoteErr: Sized {
+    fn encode_to(&self, buf: &mut BytesMut);
+    fn decode(buf: Bytes) -> Result<Self, Error>;
+}
+
+impl RemoteErr for () {
+    fn encode_to(&self, _: &mut BytesMut) {}
+
+    fn decode(_: Bytes) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+impl RemoteErr for String {
+    fn encode_to(&self, buf: &mut BytesMut) {
+        buf.put_slice(self.as_bytes());
+    }
+
+    fn decode(buf: Bytes) -> Result<Self, Error> {
+        String::from_utf8(buf.to_vec()).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+impl<T: RegistryExt> RemoteErr for T {
+    fn encode_to(&self, buf: &mut BytesMut) {
+        if let Err(err) = RegistryExt::encode_to(self, buf) {
+            tracing::error!("fail to encode remote error. {}", err);
+        }
+    }
+
+    fn decode(buf: Bytes) -> Result<Self, Error> {
+        Self::decode_frame(buf)
+    }
+}
+
+/// Accept remote links on `listener` on the current runtime until shutdown, delivering to
+/// the `exports` only
+pub(crate) fn spawn_listener<P, N, E>(
+    listener: std::net::TcpListener,
+    exports: Vec<N>,
+    router: &Router<P, N, E>,
+    shutdown: &ShutdownHandle,
+) -> Result<(), Error>
+where
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + FromStr + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    tracing::info!("accept remote links on {}", listener.local_addr()?);
+    let exports = Arc::new(exports.into_iter().collect());
+    tokio::spawn(listener::listen(
+        listener,
+        exports,
+        router.clone(),
+        shutdown.clone(),
+    ));
+    Ok(())
+}
+
+/// Route `names` to a link forwarding to the game listening on `addr`
+pub(crate) fn spawn_link<P, N, E>(
+    addr: String,
+    names: Vec<N>,
+    router: &Router<P, N, E>,
+    shutdown: &ShutdownHandle,
+) -> Result<(), Error>
+where
+    P: Proto + RegistryExt + 'static,
+    N: Name + Display + 'static,
+    E: RemoteErr + Send + 'static,
+{
+    let mut mailboxes = Vec::with_capacity(names.len());
+    for name in names {
+        let owner = format!("{} at {}", name, addr);
+        let (tx, mailbox) = mailbox::channel(&MailboxConfig::default(), owner);
+        router.insert(name.clone(), tx)?;
+        mailboxes.push((name, mailbox));
+    }
+    tokio::spawn(link::link(addr, mailboxes, shutdown.clone()));
+    Ok(())
+}
+
+// [len][frame] with a big endian u32 length, as written by RegistryExt::encode_with_len
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .length_field_length(4)
+        .big_endian()
+        .new_codec()
+}
+
+fn encode_proto<P: RegistryExt>(msg: &P) -> Result<Bytes, Error> {
+    let mut buf = BytesMut::with_capacity(msg.encoded_len());
+    msg.encode_to(&mut buf)?;
+    Ok(buf.freeze())
+}
+
+fn encode_err<E: RemoteErr>(err: &E) -> Bytes {
+    let mut buf = BytesMut::new();
+    err.encode_to(&mut buf);
+    buf.freeze()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        chanrpc::{
+            broker::Broker,
+            test_util::{self, N, P},
+            CallError, Mailbox, MailboxConfig,
+        },
+        component::{Component, ComponentBuilder},
+        gs::{GameBuilder, GameHandle},
+    };
+    use async_trait::async_trait;
+    use std::{error::Error as StdError, net::TcpListener, sync::Arc, time::Duration};
+    use tokio::sync::Notify;
+
+    type Bk = test_util::Bk<String>;
+
+    // answers Ping(n) with Pong(n + 1), Ping(0) with an error, stalls on Get until released
+    struct Comp {
+        name: N,
+        release: Arc<Notify>,
+        rx: Mailbox<P, N, String>,
+    }
+
+    #[async_trait]
+    impl Component<Bk> for Comp {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        async fn init(self: Box<Self>) -> Result<Box<dyn Component<Bk>>, Box<dyn StdError + Send>> {
+            Ok(self)
+        }
+
+        async fn run(mut self: Box<Self>) -> Result<(), Box<dyn StdError + Send>> {
+            while let Some(ctx) = self.rx.recv().await {
+                match ctx.into_parts() {
+                    (P::Shutdown, _) => break,
+                    (P::Ping(0), responder) => responder.err("zero".to_string()),
+                    (P::Ping(n), responder) => responder.ok(P::Pong(n + 1)),
+                    (P::Get, _) => self.release.notified().await,
+                    _ => (),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct CompBuilder {
+        name: N,
+        release: Arc<Notify>,
+        rx: Option<Mailbox<P, N, String>>,
+    }
+
+    impl CompBuilder {
+        fn new(name: N, release: &Arc<Notify>) -> Self {
+            Self {
+                name,
+                release: release.clone(),
+                rx: None,
+            }
+        }
+    }
+
+    impl ComponentBuilder<Bk> for CompBuilder {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        fn build(self: Box<Self>) -> Box<dyn Component<Bk>> {
+            Box::new(Comp {
+                name: self.name,
+                release: self.release,
+                rx: self.rx.unwrap(),
+            })
+        }
+
+        fn set_rx(&mut self, rx: Mailbox<P, N, String>) {
+            self.rx = Some(rx)
+        }
+
+        fn set_broker(&mut self, _: Bk) {}
+
+        fn mailbox(&self) -> MailboxConfig {
+            MailboxConfig::bounded(1)
+        }
+    }
+
+    fn game(name: N, release: &Arc<Notify>) -> GameBuilder<Bk> {
+        GameBuilder::new()
+            .handle_signals(false)
+            .current_runtime()
+            .component(CompBuilder::new(name, release))
+    }
+
+    // bound before either game serves, so links may connect first
+    fn bind() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    // wait until the link took every message queued for `name`
+    async fn forwarded(game: &GameHandle<Bk>, name: N) {
+        while game
+            .metrics_snapshot()
+            .unwrap()
+            .mailbox(&name)
+            .unwrap()
+            .depth
+            > 0
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn call_over_tcp() {
+        let release = Arc::new(Notify::new());
+        let (listener, addr) = bind();
+        // the connection of the link waits in the backlog until the listening game is up
+        let gate = game(N::Gate, &release).remote_link(addr, [N::Db]);
+        let gate_handle = gate.game_handle();
+        let gate_shutdown = gate.shutdown_handle();
+        let gate = gate.serve().unwrap();
+        let db = game(N::Db, &release).remote_listener(listener, [N::Db]);
+        let db_shutdown = db.shutdown_handle();
+        let db = db.serve().unwrap();
+
+        let broker = gate_handle.broker(N::Gate).unwrap();
+        let timeout = Duration::from_secs(5);
+        let pong = broker.call_timeout(N::Db, P::Ping(1), timeout).await;
+        assert_eq!(pong.unwrap(), P::Pong(2));
+        match broker.call_timeout(N::Db, P::Ping(0), timeout).await {
+            Err(CallError::Callee(err)) => assert_eq!(err, "zero"),
+            ret => panic!("unexpected {:?}", ret),
+        }
+        broker.cast(N::Db, P::Pong(0)).await.unwrap();
+        assert_eq!(broker.call(N::Db, P::Ping(7)).await.unwrap(), P::Pong(8));
+
+        gate_shutdown.shutdown();
+        db_shutdown.shutdown();
+        assert!(gate.await.is_ok());
+        assert!(db.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn full_mailbox_does_not_stall_link() {
+        let release = Arc::new(Notify::new());
+        let (listener, addr) = bind();
+        let db = game(N::Db, &release)
+            .component(CompBuilder::new(N::Mail, &release))
+            .remote_listener(listener, [N::Db, N::Mail]);
+        let db_shutdown = db.shutdown_handle();
+        let db = db.serve().unwrap();
+        let gate = game(N::Gate, &release).remote_link(addr, [N::Db, N::Mail]);
+        let gate_handle = gate.game_handle();
+        let gate_shutdown = gate.shutdown_handle();
+        let gate = gate.serve().unwrap();
+
+        // Db stalls on Get while its only slot fills up, the link keeps serving Mail
+        let broker = gate_handle.broker(N::Gate).unwrap();
+        for msg in [P::Get, P::Tick, P::Tick] {
+            broker.cast(N::Db, msg).await.unwrap();
+        }
+        // the link writes the casts before it takes the call
+        forwarded(&gate_handle, N::Db).await;
+        let timeout = Duration::from_secs(5);
+        let pong = broker.call_timeout(N::Mail, P::Ping(1), timeout).await;
+        assert_eq!(pong.unwrap(), P::Pong(2));
+        release.notify_one();
+        let pong = broker.call_timeout(N::Db, P::Ping(2), timeout).await;
+        assert_eq!(pong.unwrap(), P::Pong(3));
+
+        gate_shutdown.shutdown();
+        db_shutdown.shutdown();
+        assert!(gate.await.is_ok());
+        assert!(db.await.is_ok());
+    }
+}
@@ -0,0 +1,283 @@
+use super::{ChanCtx, MailboxTx, Metrics, MetricsSnapshot};
+use crate::error::Error;
+use futures::future::join_all;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, PoisonError, RwLock},
+};
+use tokio::sync::mpsc::error::TrySendError;
+
+#[derive(Debug)]
+struct Routes<P, N, E> {
+    txs: HashMap<N, MailboxTx<P, N, E>>,
+    // components removed while the game is running, until they stopped
+    retired: HashSet<N>,
+}
+
+/// Outcome of a cast sent to several components
+#[derive(Debug)]
+pub struct CastReport<N> {
+    /// number of mailboxes the message was queued in
+    pub sent: usize,
+    /// targets that did not receive the message
+    pub failed: Vec<(N, Error)>,
+}
+
+impl<N> CastReport<N> {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Routing table shared by every broker of a game.
+///
+/// Components spawned or retired while the game runs are visible to all brokers at once.
+#[derive(Debug)]
+pub struct Router<P, N, E> {
+    routes: Arc<RwLock<Routes<P, N, E>>>,
+    // stats of the routed mailboxes
+    metrics: Metrics<N>,
+}
+
+impl<P, N, E> Clone for Router<P, N, E> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<P, N, E> Default for Router<P, N, E> {
+    fn default() -> Self {
+        Self {
+            routes: Arc::new(RwLock::new(Routes {
+                txs: HashMap::new(),
+                retired: HashSet::new(),
+            })),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl<P, N, E> Router<P, N, E>
+where
+    N: Hash + Eq + Clone + Debug,
+{
+    /// mailbox of the component `name`
+    pub fn get(&self, name: &N) -> Result<MailboxTx<P, N, E>, Error> {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        match routes.txs.get(name) {
+            Some(tx) => Ok(tx.clone()),
+            None if routes.retired.contains(name) => {
+                Err(Error::ComponentRetired(format!("{:?}", name)))
+            }
+            None => Err(Error::UnknownComponent(format!("{:?}", name))),
+        }
+    }
+
+    pub fn contains(&self, name: &N) -> bool {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        routes.txs.contains_key(name)
+    }
+
+    /// names of every routable component
+    pub fn names(&self) -> Vec<N> {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        routes.txs.keys().cloned().collect()
+    }
+
+    /// queue depth, traffic and call latency of every routed mailbox
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot<N> {
+        self.metrics.snapshot()
+    }
+
+    pub(crate) fn metrics(&self) -> Metrics<N> {
+        self.metrics.clone()
+    }
+
+    /// Send every ctx to its target concurrently, following the overflow policy of each
+    /// target. A full mailbox only delays or fails its own target. Shutdown must not go
+    /// through here, a dropping or rejecting mailbox could lose it, see
+    /// [`Self::send_system_each`].
+    pub(crate) async fn send_each(&self, targets: Vec<(N, ChanCtx<P, N, E>)>) -> CastReport<N> {
+        let mut failed = Vec::new();
+        let mut sending = Vec::with_capacity(targets.len());
+        for (name, ctx) in targets {
+            match self.get(&name) {
+                Ok(tx) => {
+                    sending.push(async move {
+                        let ret = tx.deliver(ctx).await;
+                        (name, ret)
+                    });
+                }
+                Err(err) => failed.push((name, err)),
+            }
+        }
+        let mut sent = 0;
+        for (name, ret) in join_all(sending).await {
+            match ret {
+                Ok(_) => sent += 1,
+                Err(TrySendError::Full(_)) => {
+                    let err = Error::MailboxFull(format!("{:?}", name));
+                    failed.push((name, err));
+                }
+                Err(TrySendError::Closed(_)) => {
+                    let err = Error::MailboxClosed(format!("{:?}", name));
+                    failed.push((name, err));
+                }
+            }
+        }
+        CastReport { sent, failed }
+    }
+
+    /// send every ctx through the system lane of its target, whatever its overflow policy
+    pub(crate) fn send_system_each(&self, targets: Vec<(N, ChanCtx<P, N, E>)>) -> CastReport<N> {
+        let mut report = CastReport {
+            sent: 0,
+            failed: Vec::new(),
+        };
+        for (name, ctx) in targets {
+            match self.get(&name) {
+                Ok(tx) if tx.send_system(ctx).is_ok() => report.sent += 1,
+                Ok(_) => {
+                    let err = Error::MailboxClosed(format!("{:?}", name));
+                    report.failed.push((name, err));
+                }
+                Err(err) => report.failed.push((name, err)),
+            }
+        }
+        report
+    }
+
+    pub(crate) fn is_retired(&self, name: &N) -> bool {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        routes.retired.contains(name)
+    }
+
+    /// add the route of a new component. fails if a live component already has the name
+    pub(crate) fn insert(&self, name: N, tx: MailboxTx<P, N, E>) -> Result<(), Error> {
+        let mut routes = self.routes.write().unwrap_or_else(PoisonError::into_inner);
+        if routes.txs.contains_key(&name) {
+            return Err(Error::DuplicateComponent(format!("{:?}", name)));
+        }
+        routes.retired.remove(&name);
+        self.metrics.insert(name.clone(), tx.stats());
+        routes.txs.insert(name, tx);
+        Ok(())
+    }
+
+    /// remove the route, later lookups fail with ComponentRetired
+    pub(crate) fn retire(&self, name: &N) -> Result<MailboxTx<P, N, E>, Error> {
+        let mut routes = self.routes.write().unwrap_or_else(PoisonError::into_inner);
+        match routes.txs.remove(name) {
+            Some(tx) => {
+                self.metrics.remove(name);
+                routes.retired.insert(name.clone());
+                Ok(tx)
+            }
+            None if routes.retired.contains(name) => {
+                Err(Error::ComponentRetired(format!("{:?}", name)))
+            }
+            None => Err(Error::UnknownComponent(format!("{:?}", name))),
+        }
+    }
+
+    /// Forget a retired component once it stopped, later lookups fail with UnknownComponent.
+    /// false if `name` is not retired
+    pub(crate) fn forget_retired(&self, name: &N) -> bool {
+        let mut routes = self.routes.write().unwrap_or_else(PoisonError::into_inner);
+        routes.retired.remove(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Router;
+    use crate::{
+        chanrpc::{mailbox, test_util::P, ChanCtx, MailboxConfig, Overflow},
+        error::Error,
+    };
+
+    #[test]
+    fn retire_and_respawn() {
+        let router = Router::<(), &str, ()>::default();
+        let (tx, _rx) = mailbox::channel(&MailboxConfig::default(), "a".to_string());
+        router.insert("a", tx.clone()).unwrap();
+        assert!(matches!(
+            router.insert("a", tx.clone()),
+            Err(Error::DuplicateComponent(_))
+        ));
+        assert!(matches!(router.get(&"b"), Err(Error::UnknownComponent(_))));
+
+        router.retire(&"a").unwrap();
+        assert!(router.is_retired(&"a"));
+        assert!(matches!(router.get(&"a"), Err(Error::ComponentRetired(_))));
+        assert!(matches!(
+            router.retire(&"a"),
+            Err(Error::ComponentRetired(_))
+        ));
+
+        router.insert("a", tx).unwrap();
+        assert!(router.get(&"a").is_ok());
+        assert_eq!(router.names(), vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn send_each_reports_failures() {
+        let router = Router::<P, &str, ()>::default();
+        let config = MailboxConfig::default();
+        let (a, mut a_rx) = mailbox::channel(&config, "a".to_string());
+        let (b, b_rx) = mailbox::channel(&config, "b".to_string());
+        let (c, _c_rx) = mailbox::channel(&config, "c".to_string());
+        router.insert("a", a).unwrap();
+        router.insert("b", b).unwrap();
+        router.insert("c", c).unwrap();
+        let b_slot = b_rx.slot();
+        drop(b_rx);
+        b_slot.close();
+        router.retire(&"c").unwrap();
+
+        let targets = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|name| (name, ChanCtx::new_cast(P::Tick, "test")))
+            .collect();
+        let report = router.send_each(targets).await;
+        assert_eq!(report.sent, 1);
+        let failed: Vec<_> = report.failed.iter().map(|(name, _)| *name).collect();
+        assert_eq!(failed.len(), 3);
+        assert!(["b", "c", "d"].iter().all(|name| failed.contains(name)));
+        assert!(a_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown_reaches_full_mailboxes() {
+        let router = Router::<P, &str, ()>::default();
+        let mut rxs = Vec::new();
+        for (name, overflow) in [("reject", Overflow::Reject), ("drop", Overflow::DropNewest)] {
+            let config = MailboxConfig::bounded(1).overflow(overflow);
+            let (tx, rx) = mailbox::channel(&config, name.to_string());
+            tx.try_send(ChanCtx::new_cast(P::Tick, "test")).unwrap();
+            router.insert(name, tx).unwrap();
+            rxs.push(rx);
+        }
+        let targets = || {
+            ["reject", "drop"]
+                .into_iter()
+                .map(|name| (name, ChanCtx::new_cast(P::Shutdown, "test")))
+                .collect()
+        };
+        let report = router.send_each(targets()).await;
+        assert!(report.failed.iter().any(|(name, _)| *name == "reject"));
+
+        let report = router.send_system_each(targets());
+        assert_eq!(report.sent, 2);
+        assert!(report.failed.is_empty());
+        for mut rx in rxs {
+            assert_eq!(rx.recv().await.unwrap().payload(), &P::Shutdown);
+            assert_eq!(rx.recv().await.unwrap().payload(), &P::Tick);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::{CallError, StreamClosed};
+use futures::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Reply sender of a streaming call, see [`super::Responder::stream`]. The stream ends when
+/// every `StreamTx` of the call is dropped.
+#[derive(Debug)]
+pub struct StreamTx<P, E> {
+    tx: mpsc::Sender<Result<P, CallError<E>>>,
+}
+
+impl<P, E> Clone for StreamTx<P, E> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<P, E> StreamTx<P, E> {
+    /// send one item, waiting while the caller is `buffer` items behind. Fails once the
+    /// caller dropped the stream, the item comes back
+    pub async fn send(&self, item: Result<P, E>) -> Result<(), StreamClosed<Result<P, E>>> {
+        let item = item.map_err(CallError::Callee);
+        self.tx
+            .send(item)
+            .await
+            .map_err(|err| StreamClosed(err.0.map_err(into_callee)))
+    }
+
+    pub(crate) fn try_send(
+        &self,
+        item: Result<P, CallError<E>>,
+    ) -> Result<(), TrySendError<Result<P, CallError<E>>>> {
+        self.tx.try_send(item)
+    }
+
+    /// true once the caller dropped the stream
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// wait until the caller dropped the stream, to stop producing items early
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+/// Items replied to a streaming call, callee errors arrive as `CallError::Callee`. A call the
+/// callee dropped unanswered or let expire ends with `CallError::NoReply` or
+/// `CallError::Expired`. Dropping the stream cancels the call, the callee sees a closed
+/// [`StreamTx`]
+#[derive(Debug)]
+pub struct ReplyStream<P, E> {
+    rx: mpsc::Receiver<Result<P, CallError<E>>>,
+}
+
+impl<P, E> ReplyStream<P, E> {
+    /// stop the callee while still receiving the items already sent
+    pub fn close(&mut self) {
+        self.rx.close()
+    }
+}
+
+impl<P, E> Stream for ReplyStream<P, E> {
+    type Item = Result<P, CallError<E>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+// a StreamTx only sends callee errors
+fn into_callee<E>(err: CallError<E>) -> E {
+    match err {
+        CallError::Callee(err) => err,
+        _ => unreachable!("stream sent a non callee error"),
+    }
+}
+
+/// reply channel of a streaming call holding up to `buffer` items, at least one
+pub(crate) fn channel<P, E>(buffer: usize) -> (StreamTx<P, E>, ReplyStream<P, E>) {
+    let (tx, rx) = mpsc::channel(buffer.max(1));
+    (StreamTx { tx }, ReplyStream { rx })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chanrpc::{
+        mailbox,
+        test_util::{N, P},
+        CallError, CallTx, MailboxConfig,
+    };
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn stream_pages() {
+        let (tx, mut rx) = mailbox::channel::<P, N, &str>(&MailboxConfig::default(), "a".into());
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let mut sent = Vec::new();
+            while let Some(ctx) = rx.recv().await {
+                let (payload, responder) = ctx.into_parts();
+                let pages = match payload {
+                    P::Ping(pages) => pages,
+                    // the caller sees the responder dropped
+                    P::Tick => continue,
+                    // a plain reply is the only item of the stream
+                    _ => {
+                        responder.ok(P::Count(0));
+                        continue;
+                    }
+                };
+                let stream = responder.stream().unwrap();
+                for page in 1..=pages {
+                    if stream.send(Ok(P::Pong(page))).await.is_err() {
+                        break;
+                    }
+                    sent.push(page);
+                }
+                if pages == 2 {
+                    stream.send(Err("last page")).await.unwrap();
+                }
+            }
+            done_tx.send(sent).unwrap();
+        });
+        let call_tx = CallTx::new(N::Gate, tx);
+
+        let pages: Vec<_> = call_tx
+            .call_stream(P::Ping(2), 1)
+            .await
+            .unwrap()
+            .map(|page| page.map_err(CallError::into_callee))
+            .collect()
+            .await;
+        assert_eq!(
+            pages,
+            vec![Ok(P::Pong(1)), Ok(P::Pong(2)), Err(Some("last page"))]
+        );
+
+        let items: Vec<_> = call_tx
+            .call_stream(P::Get, 0)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(matches!(items[..], [Ok(P::Count(0))]));
+
+        let items: Vec<_> = call_tx
+            .call_stream(P::Tick, 1)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(matches!(items[..], [Err(CallError::NoReply)]));
+
+        // take one page of many and cancel
+        let mut stream = call_tx.call_stream(P::Ping(1000), 1).await.unwrap();
+        assert!(matches!(stream.next().await, Some(Ok(P::Pong(1)))));
+        drop(stream);
+        drop(call_tx);
+        let sent = done_rx.await.unwrap();
+        assert!(sent.len() < 100);
+    }
+}
@@ -0,0 +1,143 @@
+// names and protos shared by the tests of the crate
+use super::{broker::MapBroker, Name, Proto};
+use crate::{error::Error, registry::RegistryExt};
+use bytes::{Buf, BufMut, Bytes};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum N {
+    Gate,
+    Player,
+    Db,
+    Mail,
+    Quest,
+    Gm,
+    Ticker,
+    Counter,
+    Test,
+}
+
+impl Name for N {}
+
+impl Display for N {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for N {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Gate" => Ok(N::Gate),
+            "Player" => Ok(N::Player),
+            "Db" => Ok(N::Db),
+            "Mail" => Ok(N::Mail),
+            "Quest" => Ok(N::Quest),
+            "Gm" => Ok(N::Gm),
+            "Ticker" => Ok(N::Ticker),
+            "Counter" => Ok(N::Counter),
+            "Test" => Ok(N::Test),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum P {
+    Shutdown,
+    Tick,
+    Get,
+    Count(u32),
+    Ping(u32),
+    Pong(u32),
+}
+
+impl Proto for P {
+    fn proto_shutdown() -> Self {
+        P::Shutdown
+    }
+}
+
+// [msgid][u32] by hand, registries are usually derived
+impl RegistryExt for P {
+    const COUNT: usize = 6;
+    const NAMES: Lazy<Vec<&'static str>> =
+        Lazy::new(|| vec!["Shutdown", "Tick", "Get", "Count", "Ping", "Pong"]);
+    const IDS: Lazy<Vec<i32>> = Lazy::new(|| vec![0, 1, 2, 3, 4, 5]);
+    const ID2NAME_MAP: Lazy<HashMap<i32, &'static str>> = Lazy::new(HashMap::new);
+    const NAME2ID_MAP: Lazy<HashMap<&'static str, i32>> = Lazy::new(HashMap::new);
+    const NAME_MAP: Lazy<HashMap<&'static str, Self>> = Lazy::new(HashMap::new);
+    const ID_MAP: Lazy<HashMap<i32, Self>> = Lazy::new(HashMap::new);
+
+    fn name(&self) -> &'static str {
+        match self {
+            P::Shutdown => "Shutdown",
+            P::Tick => "Tick",
+            P::Get => "Get",
+            P::Count(_) => "Count",
+            P::Ping(_) => "Ping",
+            P::Pong(_) => "Pong",
+        }
+    }
+
+    fn msgid(&self) -> i32 {
+        match self {
+            P::Shutdown => 0,
+            P::Tick => 1,
+            P::Get => 2,
+            P::Count(_) => 3,
+            P::Ping(_) => 4,
+            P::Pong(_) => 5,
+        }
+    }
+
+    fn decode_frame<B: Buf>(mut buf: B) -> Result<Self, Error> {
+        if buf.remaining() < 8 {
+            return Err(Error::FrameFormat);
+        }
+        match (buf.get_i32(), buf.get_u32()) {
+            (0, _) => Ok(P::Shutdown),
+            (1, _) => Ok(P::Tick),
+            (2, _) => Ok(P::Get),
+            (3, n) => Ok(P::Count(n)),
+            (4, n) => Ok(P::Ping(n)),
+            (5, n) => Ok(P::Pong(n)),
+            (id, _) => Err(Error::UnknownPB(id)),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        8
+    }
+
+    fn encode_to<B: BufMut>(&self, buf: &mut B) -> Result<(), Error> {
+        buf.put_i32(self.msgid());
+        buf.put_u32(match self {
+            P::Count(n) | P::Ping(n) | P::Pong(n) => *n,
+            _ => 0,
+        });
+        Ok(())
+    }
+
+    fn encode(&self) -> Bytes {
+        let mut buf = Vec::new();
+        RegistryExt::encode_to(self, &mut buf).unwrap();
+        buf.into()
+    }
+
+    fn encode_to_with_len<B: BufMut>(&self, buf: &mut B) -> Result<(), Error> {
+        buf.put_u32(self.encoded_len() as u32);
+        RegistryExt::encode_to(self, buf)
+    }
+
+    fn encode_with_len(&self) -> Bytes {
+        let mut buf = Vec::new();
+        self.encode_to_with_len(&mut buf).unwrap();
+        buf.into()
+    }
+}
+
+pub(crate) type Bk<E = ()> = MapBroker<P, N, E>;
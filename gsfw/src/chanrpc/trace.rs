@@ -0,0 +1,159 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::{Id, Instrument, Span};
+
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static CORRELATION_ID: CorrelationId;
+}
+
+/// Id shared by every chanrpc message sent on behalf of one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    /// id of the request handled by the current task, or a fresh one
+    pub fn current() -> Self {
+        CORRELATION_ID
+            .try_with(|id| *id)
+            .unwrap_or_else(|_| Self::next())
+    }
+
+    pub fn next() -> Self {
+        Self(NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for CorrelationId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Tracing context carried by a [`super::ChanCtx`] from sender to receiver.
+///
+/// ```ignore
+/// let ctx = self.rx.recv().await?;
+/// let trace = ctx.trace().clone();
+/// trace
+///     .scope(async move {
+///         // logged inside a span following the sender's span, calls made here keep the id
+///         tracing::debug!("load player {}", id);
+///         let player = self.broker.call(Name::Db, Proto::LoadPlayer(id)).await;
+///         ctx.ok(player.unwrap_or(Proto::NotFound));
+///     })
+///     .await;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Trace {
+    correlation_id: CorrelationId,
+    // the message does not hold the sender's span open, it may close before the handling
+    sender_span: Option<Id>,
+}
+
+impl Trace {
+    /// trace of a message sent from the current task and span
+    pub fn current() -> Self {
+        Self {
+            correlation_id: CorrelationId::current(),
+            sender_span: Span::current().id(),
+        }
+    }
+
+    /// `None` if the sender has no span, e.g. a remote peer
+    pub fn new(correlation_id: CorrelationId, sender_span: Option<Id>) -> Self {
+        Self {
+            correlation_id,
+            sender_span,
+        }
+    }
+
+    pub fn correlation_id(&self) -> CorrelationId {
+        self.correlation_id
+    }
+
+    /// id of the span that was current when the message was sent
+    pub fn sender_span(&self) -> Option<&Id> {
+        self.sender_span.as_ref()
+    }
+
+    /// span of the handling, tagged with the correlation id and following the sender's span
+    pub fn span(&self) -> Span {
+        let span = tracing::debug_span!("chanrpc", correlation_id = %self.correlation_id);
+        if let Some(sender_span) = &self.sender_span {
+            span.follows_from(sender_span.clone());
+        }
+        span.or_current()
+    }
+
+    /// Run `fut` inside [`Trace::span`]. Messages sent by `fut` carry the same correlation id.
+    pub async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        CORRELATION_ID
+            .scope(self.correlation_id, fut.instrument(self.span()))
+            .await
+    }
+
+    /// blocking version of [`Trace::scope`]
+    pub fn sync_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        CORRELATION_ID.sync_scope(self.correlation_id, || self.span().in_scope(f))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CorrelationId, Trace};
+    use crate::chanrpc::{test_util::P, ChanCtx};
+    use tracing_subscriber::{
+        layer::{Identity, SubscriberExt},
+        registry::LookupSpan,
+        Registry,
+    };
+
+    #[tokio::test]
+    async fn scope_keeps_correlation_id() {
+        let trace = Trace::current();
+        let id = trace.correlation_id();
+        assert_ne!(CorrelationId::current(), id);
+        let inner = trace.scope(async { Trace::current() }).await;
+        assert_eq!(inner.correlation_id(), id);
+        assert_eq!(trace.sync_scope(CorrelationId::current), id);
+    }
+
+    #[tokio::test]
+    async fn sender_span_closes_while_queued() {
+        // spans close in a registry layered under a subscriber
+        let subscriber = Registry::default().with(Identity::new());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let sender = tracing::debug_span!("sender");
+        let sender_id = sender.id().unwrap();
+        let ctx = sender.in_scope(|| ChanCtx::<_, (), ()>::new_cast(P::Tick, ()));
+        assert_eq!(ctx.trace().sender_span(), Some(&sender_id));
+
+        // queued while the sender's span closes, handled after
+        drop(sender);
+        let closed = tracing::dispatcher::get_default(|dispatch| {
+            let registry = dispatch.downcast_ref::<Registry>().unwrap();
+            registry.span(&sender_id).is_none()
+        });
+        assert!(closed);
+        let handled = ctx
+            .trace()
+            .scope(async { tracing::Span::current().id() })
+            .await;
+        assert!(handled.is_some());
+    }
+}
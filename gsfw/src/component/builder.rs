@@ -1,6 +1,7 @@
-use tokio::sync::mpsc;
-
-use crate::chanrpc::{broker::Broker, ChanCtx};
+use crate::{
+    chanrpc::{broker::Broker, Mailbox, MailboxConfig},
+    gs::RuntimeMode,
+};
 
 pub trait ComponentBuilder<B>
 where
@@ -9,8 +10,22 @@ where
     // component name
     fn name(&self) -> B::Name;
     fn build(self: Box<Self>) -> Box<dyn super::Component<B>>;
-    fn set_rx(&mut self, rx: mpsc::Receiver<ChanCtx<B::Proto, B::Name, B::Err>>);
+    fn set_rx(&mut self, rx: Mailbox<B::Proto, B::Name, B::Err>);
     fn set_broker(&mut self, broker: B);
+    // channel of the mailbox, read once before the mailbox is created
+    fn mailbox(&self) -> MailboxConfig {
+        MailboxConfig::default()
+    }
+    // components that must complete init before this one starts init,
+    // and are shutdown after this one stopped
+    fn dependencies(&self) -> Vec<B::Name> {
+        Vec::new()
+    }
+    // run on a dedicated thread or as a task on a shared runtime
+    fn runtime_mode(&self) -> RuntimeMode {
+        RuntimeMode::Dedicated
+    }
+    // runtime of the dedicated thread, only used with RuntimeMode::Dedicated
     fn runtime(&self) -> tokio::runtime::Runtime {
         tokio::runtime::Builder::new_current_thread()
             .enable_io()
@@ -28,4 +28,26 @@ pub enum Error {
     Encode(String),
     #[error("mismatch variant when cast to {0}")]
     VariantCast(&'static str),
+    #[error("component {0} depends on unregistered component {1}")]
+    UnknownDependency(String, String),
+    #[error("dependency cycle between components {0}")]
+    DependencyCycle(String),
+    #[error("dependency {0} stopped before init complete")]
+    DependencyStopped(String),
+    #[error("runtime pool {0} is not registered")]
+    UnknownRuntimePool(String),
+    #[error("component {0} not found")]
+    UnknownComponent(String),
+    #[error("component {0} has been retired")]
+    ComponentRetired(String),
+    #[error("component {0} is already running")]
+    DuplicateComponent(String),
+    #[error("mailbox of component {0} is closed")]
+    MailboxClosed(String),
+    #[error("mailbox of component {0} is full")]
+    MailboxFull(String),
+    #[error("game is not serving")]
+    NotServing,
+    #[error("game is shutting down")]
+    ShuttingDown,
 }
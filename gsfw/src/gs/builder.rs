@@ -1,15 +1,50 @@
+use futures::stream::FuturesUnordered;
 use tokio::sync::mpsc;
 use tracing::{instrument, Instrument};
 
 use crate::{
-    chanrpc::{broker::Broker, ChanCtx},
+    chanrpc::{
+        broker::Broker,
+        pubsub::Bus,
+        remote::{self, RemoteErr},
+        ChanCtx, Router,
+    },
     component::ComponentBuilder,
+    error::Error,
+    registry::RegistryExt,
 };
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+    collections::HashSet,
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    launcher::{ExitHooks, Launcher, Registration},
+    runtime::{PoolConfig, Runtimes},
+    state::{self, States},
+    ComponentState, GameHandle, ShutdownHandle, Supervisor,
+};
+
+type RouterOf<B> = Router<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>;
+
+// starts a remote listener or link once the game serves
+type RemoteSetup<B> = Box<dyn FnOnce(&RouterOf<B>, &ShutdownHandle) -> Result<(), Error>>;
 
 pub struct GameBuilder<B: Broker> {
     component_set: HashSet<B::Name>,
-    component_builders: Vec<Box<dyn ComponentBuilder<B>>>,
+    component_builders: Vec<Registration<B>>,
+    router: Router<B::Proto, B::Name, B::Err>,
+    remotes: Vec<RemoteSetup<B>>,
+    exit_hooks: ExitHooks<B::Name>,
+    shutdown: ShutdownHandle,
+    game_handle: GameHandle<B>,
+    grace_timeout: Option<Duration>,
+    handle_signals: bool,
+    pools: PoolConfig,
 }
 
 impl<B: Broker + 'static> GameBuilder<B> {
@@ -17,110 +52,302 @@ impl<B: Broker + 'static> GameBuilder<B> {
         Self {
             component_set: Default::default(),
             component_builders: Default::default(),
+            router: Default::default(),
+            remotes: Default::default(),
+            exit_hooks: Default::default(),
+            shutdown: Default::default(),
+            game_handle: GameHandle::new(),
+            grace_timeout: None,
+            handle_signals: true,
+            pools: Default::default(),
         }
     }
 
+    /// handle to trigger the shutdown of the game being built.
+    /// it can be handed to component builders before they are registered
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// handle to add and retire components once the game is serving.
+    /// it can be handed to component builders before they are registered
+    pub fn game_handle(&self) -> GameHandle<B> {
+        self.game_handle.clone()
+    }
+
+    /// Topic bus shared by the components of this game, keyed by `T`.
+    /// Every call creates an independent bus, hand clones of it to the component builders.
+    pub fn bus<T>(&self) -> Bus<T, B>
+    where
+        T: Hash + Eq + Clone + Debug + Send + Sync + 'static,
+    {
+        let bus = Bus::new(self.router.clone());
+        let subscriptions = bus.clone();
+        self.exit_hooks
+            .push(move |name| subscriptions.unsubscribe_all(name));
+        bus
+    }
+
+    /// how long to wait for components after shutdown begins.
+    /// components still running after the timeout are reported and abandoned.
+    /// default is waiting forever
+    pub fn grace_timeout(mut self, timeout: Duration) -> Self {
+        self.grace_timeout = Some(timeout);
+        self
+    }
+
+    /// whether SIGINT, SIGTERM and SIGHUP trigger the shutdown. default is true
+    pub fn handle_signals(mut self, enable: bool) -> Self {
+        self.handle_signals = enable;
+        self
+    }
+
+    /// worker threads of the runtime shared by `RuntimeMode::Shared` components.
+    /// default is the number of CPU cores
+    pub fn shared_runtime(mut self, worker_threads: usize) -> Self {
+        self.pools.shared = Some(worker_threads);
+        self
+    }
+
+    /// register a named runtime pool for `RuntimeMode::Pool` components.
+    /// the pool is only built if a component runs on it
+    pub fn runtime_pool(mut self, name: impl Into<String>, worker_threads: usize) -> Self {
+        self.pools.named.insert(name.into(), Some(worker_threads));
+        self
+    }
+
+    /// run every component as a task on the runtime calling `serve`, whatever its RuntimeMode
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn current_runtime(mut self) -> Self {
+        self.pools.current = true;
+        self
+    }
+
+    /// register a component. it stays stopped once `init` or `run` fails
     #[instrument(level="info", skip_all, name="add_component", fields(name=?component_builder.name()))]
-    pub fn component<CB>(mut self, component_builder: CB) -> Self
+    pub fn component<CB>(self, component_builder: CB) -> Self
+    where
+        CB: ComponentBuilder<B> + 'static,
+    {
+        self.register(Registration {
+            builder: Box::new(component_builder),
+            supervisor: Supervisor::Ignore,
+            factory: None,
+        })
+    }
+
+    /// register a component built by `factory` under the given supervisor policy.
+    /// `factory` is called again for every restart, the restarted component keeps its channel
+    #[instrument(level = "info", skip_all, name = "add_component", fields(?supervisor))]
+    pub fn supervised_component<CB, F>(self, supervisor: Supervisor, factory: F) -> Self
     where
         CB: ComponentBuilder<B> + 'static,
+        F: Fn() -> CB + Send + 'static,
     {
-        if let Some(_) = self.component_set.get(&component_builder.name()) {
-            panic!(
-                "component[{:?}] already registered",
-                component_builder.name()
-            );
+        self.register(Registration {
+            builder: Box::new(factory()),
+            supervisor,
+            factory: Some(Box::new(move || Box::new(factory()))),
+        })
+    }
+
+    fn register(mut self, registration: Registration<B>) -> Self {
+        let name = registration.builder.name();
+        if self.component_set.contains(&name) {
+            panic!("component[{:?}] already registered", name);
         }
-        self.component_set.insert(component_builder.name());
-        self.component_builders.push(Box::new(component_builder));
+        self.component_set.insert(name);
+        self.component_builders.push(registration);
         self
     }
 
-    #[instrument(level = "info", skip(self), name="game_serve")]
+    #[instrument(level = "info", skip(self), name = "game_serve")]
     pub fn serve(self) -> Result<super::Game<B::Name>, crate::error::Error> {
-        if self.component_builders.len() == 0 {
+        if self.component_builders.is_empty() {
             return Err(crate::error::Error::NoComponent);
         }
         let names: Vec<_> = self
             .component_builders
             .iter()
-            .map(|builder| builder.name())
+            .map(|reg| reg.builder.name())
             .collect();
 
-        let chans: Vec<_> = self
+        let dependencies: Vec<_> = self
             .component_builders
             .iter()
-            .map(|_| mpsc::channel(1024))
+            .map(|reg| (reg.builder.name(), reg.builder.dependencies()))
             .collect();
-        let txs: Vec<_> = chans.iter().map(|(tx, _)| tx.clone()).collect();
-        let mut rxs: VecDeque<_> = chans.into_iter().map(|(_, rx)| rx).collect();
-
-        let tx_pairs: Vec<(_, _)> = std::iter::zip(
-            names.iter().map(|n| n.clone()),
-            txs.iter().map(|tx| tx.clone()),
-        )
-        .collect();
-        let tx_map: HashMap<_, _> = tx_pairs
-            .iter()
-            .map(|(n, tx)| (n.clone(), tx.clone()))
+        let stages: Vec<Vec<_>> = super::deps::stages(&dependencies)?
+            .into_iter()
+            .map(|stage| stage.into_iter().map(|i| names[i].clone()).collect())
             .collect();
+        tracing::debug!("startup stages: {:?}", stages);
+
+        let runtimes = Arc::new(Mutex::new(Runtimes::default()));
+        let (handles_tx, handles_rx) = mpsc::unbounded_channel();
+        let states = States::default();
+        let metrics = self.router.metrics();
+        let launcher = Arc::new(Launcher::new(
+            self.router,
+            self.shutdown.clone(),
+            states.clone(),
+            self.exit_hooks,
+            self.pools,
+            runtimes.clone(),
+            handles_tx,
+        ));
 
-        // future of SIGINT event
-        let ctrl_c_future = tokio::spawn(
+        // build the shared runtimes up front, so no component starts if one fails
+        let modes = self
+            .component_builders
+            .iter()
+            .map(|reg| launcher.runtime(&reg.builder.runtime_mode()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // every route exists before the first component starts
+        let prepared = self
+            .component_builders
+            .iter()
+            .map(|reg| launcher.prepare(reg.builder.as_ref(), false))
+            .collect::<Result<Vec<_>, _>>()?;
+        // the remote tasks stop on shutdown, so a failing setup takes down those started
+        for setup in self.remotes {
+            if let Err(err) = setup(&launcher.router, &self.shutdown) {
+                self.shutdown.shutdown();
+                return Err(err);
+            }
+        }
+
+        // future of shutdown event
+        let shutdown = self.shutdown.clone();
+        let handle_signals = self.handle_signals;
+        let grace_timeout = self.grace_timeout;
+        let shutdown_launcher = launcher.clone();
+        let shutdown_future = tokio::spawn(
             async move {
-                if let Err(err) = tokio::signal::ctrl_c().await {
-                    tracing::error!("ctrl_c error: {}", err);
+                tokio::select! {
+                    _ = shutdown.wait() => tracing::debug!("shutdown requested, begin to clean up"),
+                    sig = super::shutdown::recv_signal(), if handle_signals => {
+                        tracing::debug!("{} received, begin to clean up", sig);
+                        shutdown.shutdown();
+                    }
                 }
-                tracing::debug!("CTRL+C pressed, begin to clean up");
-                // prevent blocking the task drive thread
-                for (k, tx) in tx_pairs {
-                    tracing::trace!("sending shutdown to {:?}", k);
-                    if let Err(err) = tx
-                        .send(ChanCtx::new_cast(
-                            <B::Proto as crate::chanrpc::Proto>::proto_shutdown(),
-                            k,
-                        ))
-                        .await
-                    {
-                        tracing::error!("fail to send shutdown. {}", err);
+                let deadline = grace_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+                // dependents are shutdown before their dependencies
+                for stage in shutdown_launcher.states.shutdown_stages(&stages) {
+                    // retired and stopped components are not told twice. the system lane
+                    // lets shutdown overtake the regular messages still queued
+                    let targets: Vec<_> = stage
+                        .iter()
+                        .filter(|(_, state)| state::begin_shutdown(state))
+                        .map(|(k, _)| {
+                            tracing::trace!("sending shutdown to {:?}", k);
+                            let msg = <B::Proto as crate::chanrpc::Proto>::proto_shutdown();
+                            (k.clone(), ChanCtx::new_cast(msg, k.clone()))
+                        })
+                        .collect();
+                    let report = shutdown_launcher.router.send_system_each(targets);
+                    for (k, err) in report.failed {
+                        tracing::error!("fail to send shutdown to {:?}. {}", k, err);
+                    }
+                    for (k, state) in stage {
+                        let mut state = state.subscribe();
+                        let stopped = state.wait_for(ComponentState::is_stopped);
+                        let in_time = match deadline {
+                            Some(deadline) => {
+                                tokio::time::timeout_at(deadline, stopped).await.is_ok()
+                            }
+                            None => {
+                                let _ = stopped.await;
+                                true
+                            }
+                        };
+                        if !in_time {
+                            tracing::warn!("component {:?} not stopped before grace timeout", k);
+                        }
                     }
                 }
             }
-            .instrument(tracing::info_span!("waiting for ctrl_c...").or_current()),
+            .instrument(tracing::info_span!("waiting for shutdown...").or_current()),
         );
 
-        let component_handles = self
-            .component_builders
-            .into_iter()
-            .map(|mut builder| {
-                builder.set_broker(B::new(builder.name(), &tx_map));
-                builder.set_rx(rxs.pop_front().unwrap());
-                tracing::debug!("ComponentBuilder {:?} setup complete", builder.name());
-                let rt = builder.runtime();
-                let component = builder.build();
-                let component_name = component.name();
-                tracing::debug!("component {:?} setup complete", component.name());
-                let name = component.name();
-                let join = std::thread::spawn(move || {
-                    let ret = rt.block_on(
-                        async move { component.init().await?.run().await }.instrument(
-                            tracing::debug_span!("component", name=?component_name)
-                                .or_current(),
-                        ),
-                    );
-                    rt.shutdown_background();
-                    ret
-                });
-                super::ComponentHandle { join, name }
-            })
-            .collect();
+        for ((reg, prepared), handle) in
+            self.component_builders.into_iter().zip(prepared).zip(modes)
+        {
+            let dependencies = launcher.dependencies(reg.builder.as_ref())?;
+            launcher.launch(reg, prepared, dependencies, handle);
+        }
+        self.game_handle.attach(launcher);
         tracing::info!("all components launch complete, running: {:?}", names);
-        tracing::info!("press CTRL+C to terminate the app");
+        if self.handle_signals {
+            tracing::info!("press CTRL+C to terminate the app");
+        }
         Ok(super::Game {
-            component_handles,
-            poll_component: None,
-            ctrl_c_future,
-            ctrl_c_trigger: false,
+            component_handles: FuturesUnordered::new(),
+            reports: Vec::new(),
+            launched: handles_rx,
+            shutdown_wait: Box::pin(self.shutdown.wait_owned()),
+            shutdown: self.shutdown,
+            shutdown_future,
+            shutdown_trigger: false,
+            grace_timeout: self.grace_timeout,
+            grace: None,
+            runtimes,
+            states,
+            metrics,
         })
     }
 }
+
+impl<B> GameBuilder<B>
+where
+    B: Broker + 'static,
+    B::Proto: RegistryExt + 'static,
+    B::Name: Display + FromStr + 'static,
+    B::Err: RemoteErr + 'static,
+{
+    /// Accept remote chanrpc links on `addr` once the game serves, see [`remote`].
+    /// Messages from linked games reach the `exports` of this game as regular casts and calls,
+    /// other components are out of their reach. Links are not authenticated, keep the listener
+    /// on a trusted network
+    pub fn remote_listen(
+        mut self,
+        addr: impl Into<String>,
+        exports: impl IntoIterator<Item = B::Name>,
+    ) -> Self {
+        let addr = addr.into();
+        let exports = exports.into_iter().collect();
+        self.remotes.push(Box::new(move |router, shutdown| {
+            let listener = std::net::TcpListener::bind(&addr)?;
+            remote::spawn_listener(listener, exports, router, shutdown)
+        }));
+        self
+    }
+
+    /// [`Self::remote_listen`] on a listener bound beforehand, e.g. to an ephemeral port
+    pub fn remote_listener(
+        mut self,
+        listener: std::net::TcpListener,
+        exports: impl IntoIterator<Item = B::Name>,
+    ) -> Self {
+        let exports = exports.into_iter().collect();
+        self.remotes.push(Box::new(move |router, shutdown| {
+            remote::spawn_listener(listener, exports, router, shutdown)
+        }));
+        self
+    }
+
+    /// Route `names` to the game listening on `addr`. Casts and calls to them go over a TCP
+    /// link that reconnects on failure, `serve` fails if a local component has one of the names
+    pub fn remote_link(
+        mut self,
+        addr: impl Into<String>,
+        names: impl IntoIterator<Item = B::Name>,
+    ) -> Self {
+        let addr = addr.into();
+        let names = names.into_iter().collect();
+        self.remotes.push(Box::new(move |router, shutdown| {
+            remote::spawn_link(addr, names, router, shutdown)
+        }));
+        self
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::Error;
+use std::{collections::HashMap, fmt::Debug, hash::Hash};
+
+/// Group components into startup stages. Components of a stage only depend on components
+/// of earlier stages, so shutting down the stages in reverse order stops dependents first.
+/// `deps[i]` holds the name of component i and the names it depends on.
+pub(crate) fn stages<N>(deps: &[(N, Vec<N>)]) -> Result<Vec<Vec<usize>>, Error>
+where
+    N: Hash + Eq + Debug,
+{
+    let index: HashMap<&N, usize> = deps
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name, i))
+        .collect();
+    // dependents[i] -> components depending on component i
+    let mut dependents = vec![Vec::new(); deps.len()];
+    let mut pending: Vec<usize> = vec![0; deps.len()];
+    for (i, (name, names)) in deps.iter().enumerate() {
+        for dep in names {
+            let dep = *index.get(dep).ok_or_else(|| {
+                Error::UnknownDependency(format!("{:?}", name), format!("{:?}", dep))
+            })?;
+            dependents[dep].push(i);
+            pending[i] += 1;
+        }
+    }
+
+    let mut stages = Vec::new();
+    let mut current: Vec<usize> = (0..deps.len()).filter(|&i| pending[i] == 0).collect();
+    let mut staged = 0;
+    while !current.is_empty() {
+        staged += current.len();
+        let mut next = Vec::new();
+        for &i in &current {
+            for &dependent in &dependents[i] {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 {
+                    next.push(dependent);
+                }
+            }
+        }
+        stages.push(current);
+        current = next;
+    }
+    if staged < deps.len() {
+        let cycle: Vec<_> = (0..deps.len())
+            .filter(|&i| pending[i] > 0)
+            .map(|i| &deps[i].0)
+            .collect();
+        return Err(Error::DependencyCycle(format!("{:?}", cycle)));
+    }
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::stages;
+    use crate::error::Error;
+
+    #[test]
+    fn stage_by_dependency() {
+        let deps = vec![
+            ("player", vec!["db", "chat"]),
+            ("db", vec![]),
+            ("chat", vec!["db"]),
+            ("gate", vec![]),
+        ];
+        let stages = stages(&deps).unwrap();
+        assert_eq!(stages, vec![vec![1, 3], vec![2], vec![0]]);
+    }
+
+    #[test]
+    fn reject_cycle() {
+        let deps = vec![
+            ("a", vec!["b"]),
+            ("b", vec!["c"]),
+            ("c", vec!["a"]),
+            ("d", vec![]),
+        ];
+        assert!(matches!(stages(&deps), Err(Error::DependencyCycle(_))));
+        let deps = vec![("a", vec!["a"])];
+        assert!(matches!(stages(&deps), Err(Error::DependencyCycle(_))));
+    }
+
+    #[test]
+    fn reject_unknown() {
+        let deps = vec![("a", vec!["b"])];
+        assert!(matches!(stages(&deps), Err(Error::UnknownDependency(_, _))));
+    }
+}
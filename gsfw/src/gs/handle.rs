@@ -0,0 +1,128 @@
+use super::{
+    launcher::{Launcher, Registration},
+    ComponentState, Supervisor,
+};
+use crate::{
+    chanrpc::{broker::Broker, MetricsSnapshot},
+    component::ComponentBuilder,
+    error::Error,
+};
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Cloneable handle adding and retiring components while the game is serving.
+///
+/// Obtained from [`super::GameBuilder::game_handle`] before `serve`, so it can be handed to
+/// component builders. Calls made before `serve` or after the game resolved fail with
+/// [`Error::NotServing`].
+pub struct GameHandle<B: Broker> {
+    launcher: Arc<OnceCell<Arc<Launcher<B>>>>,
+}
+
+impl<B: Broker> Clone for GameHandle<B> {
+    fn clone(&self) -> Self {
+        Self {
+            launcher: self.launcher.clone(),
+        }
+    }
+}
+
+impl<B: Broker> std::fmt::Debug for GameHandle<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameHandle")
+            .field("serving", &self.launcher.get().is_some())
+            .finish()
+    }
+}
+
+impl<B: Broker + 'static> GameHandle<B> {
+    pub(crate) fn new() -> Self {
+        Self {
+            launcher: Default::default(),
+        }
+    }
+
+    pub(crate) fn attach(&self, launcher: Arc<Launcher<B>>) {
+        if self.launcher.set(launcher).is_err() {
+            panic!("game handle already attached");
+        }
+    }
+
+    fn launcher(&self) -> Result<&Arc<Launcher<B>>, Error> {
+        self.launcher.get().ok_or(Error::NotServing)
+    }
+
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn broker(&self, name: B::Name) -> Result<B, Error> {
+        Ok(B::new(name, &self.launcher()?.router))
+    }
+
+    // messages waiting in every routed mailbox
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn queued(&self) -> Result<usize, Error> {
+        let router = &self.launcher()?.router;
+        Ok(router
+            .names()
+            .iter()
+            .filter_map(|name| router.get(name).ok())
+            .map(|tx| tx.len())
+            .sum())
+    }
+
+    /// start a new component. its dependencies must have been launched already.
+    /// fails if a live component has the same name
+    pub fn spawn<CB>(&self, component_builder: CB) -> Result<(), Error>
+    where
+        CB: ComponentBuilder<B> + 'static,
+    {
+        self.launcher()?.spawn(Registration {
+            builder: Box::new(component_builder),
+            supervisor: Supervisor::Ignore,
+            factory: None,
+        })
+    }
+
+    /// start a new component built by `factory` under the given supervisor policy
+    pub fn spawn_supervised<CB, F>(&self, supervisor: Supervisor, factory: F) -> Result<(), Error>
+    where
+        CB: ComponentBuilder<B> + 'static,
+        F: Fn() -> CB + Send + 'static,
+    {
+        self.launcher()?.spawn(Registration {
+            builder: Box::new(factory()),
+            supervisor,
+            factory: Some(Box::new(move || Box::new(factory()))),
+        })
+    }
+
+    /// Remove a component from the routing table and send it the shutdown message.
+    /// Later sends to `name` fail with [`Error::ComponentRetired`] until it stopped, then it is
+    /// forgotten like a name never spawned. The component is never restarted once retired.
+    pub fn retire(&self, name: B::Name) -> Result<(), Error> {
+        self.launcher()?.retire(&name)
+    }
+
+    /// names of the components currently reachable by brokers
+    pub fn components(&self) -> Result<Vec<B::Name>, Error> {
+        Ok(self.launcher()?.router.names())
+    }
+
+    /// current state of every component in launch order
+    pub fn states(&self) -> Result<Vec<(B::Name, ComponentState)>, Error> {
+        Ok(self.launcher()?.states.snapshot())
+    }
+
+    /// queue depth, traffic and call latency of every routed mailbox
+    pub fn metrics_snapshot(&self) -> Result<MetricsSnapshot<B::Name>, Error> {
+        Ok(self.launcher()?.router.metrics_snapshot())
+    }
+
+    /// receiver notified on every state change of the component `name`
+    pub fn watch_state(&self, name: &B::Name) -> Result<watch::Receiver<ComponentState>, Error> {
+        match self.launcher()?.states.get(name) {
+            Some(state) => Ok(state.subscribe()),
+            None => Err(Error::UnknownComponent(format!("{:?}", name))),
+        }
+    }
+}
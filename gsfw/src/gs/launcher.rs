@@ -0,0 +1,240 @@
+use super::{
+    report::{panic_message, ComponentExit},
+    runtime::{Executor, PoolConfig, Runtimes},
+    state::{self, States},
+    supervisor::{BuilderFactory, Supervised},
+    ComponentHandle, ComponentState, RuntimeMode, ShutdownHandle, Supervisor,
+};
+use crate::{
+    chanrpc::{broker::Broker, mailbox, ChanCtx, Mailbox, Proto, Router},
+    component::ComponentBuilder,
+    error::Error,
+};
+use futures::FutureExt;
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+};
+use tracing::Instrument;
+
+pub(crate) struct Registration<B: Broker> {
+    pub(crate) builder: Box<dyn ComponentBuilder<B>>,
+    pub(crate) supervisor: Supervisor,
+    // builds fresh builders on restart
+    pub(crate) factory: Option<BuilderFactory<B>>,
+}
+
+type Prepared<B> = (
+    Mailbox<<B as Broker>::Proto, <B as Broker>::Name, <B as Broker>::Err>,
+    watch::Sender<ComponentState>,
+);
+
+type Dependencies<N> = Vec<(N, watch::Receiver<ComponentState>)>;
+
+type ExitHook<N> = Box<dyn Fn(&N) + Send + Sync>;
+
+/// Callbacks run once a component stopped for good
+pub(crate) struct ExitHooks<N>(Arc<Mutex<Vec<ExitHook<N>>>>);
+
+impl<N> Clone for ExitHooks<N> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<N> Default for ExitHooks<N> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<N> ExitHooks<N> {
+    pub(crate) fn push(&self, hook: impl Fn(&N) + Send + Sync + 'static) {
+        let mut hooks = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        hooks.push(Box::new(hook));
+    }
+
+    pub(crate) fn run(&self, name: &N) {
+        let hooks = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        for hook in hooks.iter() {
+            hook(name);
+        }
+    }
+}
+
+/// Start components of a serving game. Shared by `GameBuilder::serve`, the shutdown task
+/// and every `GameHandle`.
+pub(crate) struct Launcher<B: Broker> {
+    pub(crate) router: Router<B::Proto, B::Name, B::Err>,
+    pub(crate) shutdown: ShutdownHandle,
+    pub(crate) states: States<B::Name>,
+    exit_hooks: ExitHooks<B::Name>,
+    runtimes: Arc<Mutex<Runtimes>>,
+    pools: PoolConfig,
+    // handles are delivered to the Game, closed once the Game resolved
+    handles: mpsc::UnboundedSender<ComponentHandle<B::Name>>,
+}
+
+impl<B: Broker + 'static> Launcher<B> {
+    pub(crate) fn new(
+        router: Router<B::Proto, B::Name, B::Err>,
+        shutdown: ShutdownHandle,
+        states: States<B::Name>,
+        exit_hooks: ExitHooks<B::Name>,
+        pools: PoolConfig,
+        runtimes: Arc<Mutex<Runtimes>>,
+        handles: mpsc::UnboundedSender<ComponentHandle<B::Name>>,
+    ) -> Self {
+        Self {
+            router,
+            shutdown,
+            states,
+            exit_hooks,
+            runtimes,
+            pools,
+            handles,
+        }
+    }
+
+    /// handle of the shared runtime the component runs on. None for a dedicated thread
+    pub(crate) fn runtime(&self, mode: &RuntimeMode) -> Result<Option<Handle>, Error> {
+        if self.pools.current {
+            return Ok(Some(Handle::current()));
+        }
+        match mode {
+            RuntimeMode::Dedicated => Ok(None),
+            mode => self
+                .runtimes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .handle(mode, &self.pools)
+                .map(Some),
+        }
+    }
+
+    /// create the mailbox and route of a component, every broker can reach it from now on
+    pub(crate) fn prepare(
+        &self,
+        builder: &dyn ComponentBuilder<B>,
+        dynamic: bool,
+    ) -> Result<Prepared<B>, Error> {
+        let name = builder.name();
+        let (tx, mailbox) = mailbox::channel(&builder.mailbox(), format!("{:?}", name));
+        self.router.insert(name.clone(), tx)?;
+        Ok((mailbox, self.states.insert(name, dynamic)))
+    }
+
+    /// states of the dependencies of a component, resolved to their latest launch
+    pub(crate) fn dependencies(
+        &self,
+        builder: &dyn ComponentBuilder<B>,
+    ) -> Result<Dependencies<B::Name>, Error> {
+        builder
+            .dependencies()
+            .into_iter()
+            .map(|dep| match self.states.get(&dep) {
+                Some(state) => Ok((dep, state.subscribe())),
+                None => Err(Error::UnknownDependency(
+                    format!("{:?}", builder.name()),
+                    format!("{:?}", dep),
+                )),
+            })
+            .collect()
+    }
+
+    /// spawn a prepared component under its supervisor
+    pub(crate) fn launch(
+        &self,
+        reg: Registration<B>,
+        (mailbox, state): Prepared<B>,
+        dependencies: Dependencies<B::Name>,
+        handle: Option<Handle>,
+    ) {
+        let mut builder = reg.builder;
+        let name = builder.name();
+        let supervised = Supervised {
+            name: name.clone(),
+            supervisor: reg.supervisor,
+            factory: reg.factory,
+            mailbox: mailbox.slot(),
+            router: self.router.clone(),
+            shutdown: self.shutdown.clone(),
+            state: state.clone(),
+            states: self.states.clone(),
+            dependencies,
+            exit_hooks: self.exit_hooks.clone(),
+        };
+        builder.set_broker(B::new(name.clone(), &self.router));
+        builder.set_rx(mailbox);
+        tracing::debug!("ComponentBuilder {:?} setup complete", name);
+        let executor = match handle {
+            Some(handle) => Executor::Task(handle),
+            None => Executor::Thread(builder.runtime()),
+        };
+        let component = builder.build();
+        tracing::debug!("component {:?} setup complete", name);
+        let span = tracing::debug_span!("component", name=?name).or_current();
+        let started = Instant::now();
+        let (exit_tx, exit_rx) = tokio::sync::oneshot::channel();
+        let driver = async move {
+            let (exit, restarts) = AssertUnwindSafe(supervised.run(component))
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|payload| {
+                    let exit = ComponentExit::Panic(panic_message(payload));
+                    state.send_replace(ComponentState::Stopped(Err(exit.to_string())));
+                    (exit, 0)
+                });
+            let _ = exit_tx.send((exit, restarts, started.elapsed()));
+        }
+        .instrument(span);
+        executor.spawn(driver);
+        let _ = self.handles.send(ComponentHandle {
+            index: 0,
+            name: Some(name),
+            started,
+            exit_rx,
+        });
+    }
+
+    /// add a component to the serving game
+    pub(crate) fn spawn(&self, reg: Registration<B>) -> Result<(), Error> {
+        if self.handles.is_closed() {
+            return Err(Error::NotServing);
+        }
+        if self.shutdown.is_shutdown() {
+            return Err(Error::ShuttingDown);
+        }
+        let handle = self.runtime(&reg.builder.runtime_mode())?;
+        let dependencies = self.dependencies(reg.builder.as_ref())?;
+        let prepared = self.prepare(reg.builder.as_ref(), true)?;
+        tracing::info!("spawn component {:?}", reg.builder.name());
+        self.launch(reg, prepared, dependencies, handle);
+        Ok(())
+    }
+
+    /// remove the route of a component and ask it to shutdown
+    pub(crate) fn retire(&self, name: &B::Name) -> Result<(), Error> {
+        let tx = self.router.retire(name)?;
+        tracing::info!("retire component {:?}", name);
+        match self.states.get(name) {
+            // stopped on its own before, its supervisor is gone
+            Some(state) if state.borrow().is_stopped() => {
+                self.router.forget_retired(name);
+                self.states.remove(name, &state);
+                return Ok(());
+            }
+            Some(state) => {
+                state::begin_shutdown(&state);
+            }
+            None => (),
+        }
+        tx.send_system(ChanCtx::new_cast(B::Proto::proto_shutdown(), name.clone()))
+            .map_err(|err| Error::SendError(err.to_string()))
+    }
+}
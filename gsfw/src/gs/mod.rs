@@ -1,70 +1,218 @@
-use futures::{ready, Future, FutureExt};
+use futures::{stream::FuturesUnordered, Future, FutureExt, StreamExt};
 use pin_project::pin_project;
-use std::error::Error as StdError;
-use std::{collections::VecDeque, fmt::Debug};
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
+use tokio_util::sync::WaitForCancellationFutureOwned;
+use crate::chanrpc::{Metrics, MetricsSnapshot};
 
 mod builder;
+mod deps;
+mod handle;
+mod launcher;
+mod report;
+mod runtime;
+mod shutdown;
+mod state;
+mod supervisor;
+#[cfg(any(test, feature = "test-util"))]
+mod testing;
 pub use builder::GameBuilder;
+pub use handle::GameHandle;
+pub use report::{ComponentExit, ComponentReport, GameReport};
+pub use runtime::RuntimeMode;
+pub use shutdown::ShutdownHandle;
+pub use state::ComponentState;
+pub use supervisor::{RestartPolicy, Supervisor};
+#[cfg(any(test, feature = "test-util"))]
+pub use testing::TestGame;
 
+// resolve when the component thread exits
 #[derive(Debug)]
-struct ComponentHandle<N> {
-    // rt: tokio::runtime::Runtime,
-    // join: JoinHandle<Result<(), Box<dyn StdError + Send>>>,
-    join: std::thread::JoinHandle<Result<(), Box<dyn StdError + Send>>>,
-    name: N,
+pub(crate) struct ComponentHandle<N> {
+    // launch order of the component, assigned once the Game receives the handle
+    index: usize,
+    name: Option<N>,
+    started: Instant,
+    // last exit, restart count and total running time
+    exit_rx: oneshot::Receiver<(ComponentExit, u32, Duration)>,
 }
 
+// fields are never pinned, exit_rx is polled through Unpin
+impl<N> Unpin for ComponentHandle<N> {}
+
+impl<N> ComponentHandle<N> {
+    fn abandon(mut self) -> (usize, ComponentReport<N>) {
+        let report = ComponentReport {
+            name: self.name.take().expect("component handle already resolved"),
+            exit: ComponentExit::Abandoned,
+            restarts: 0,
+            elapsed: self.started.elapsed(),
+        };
+        (self.index, report)
+    }
+}
+
+impl<N> Future for ComponentHandle<N> {
+    type Output = (usize, ComponentReport<N>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let (exit, restarts, elapsed) = match futures::ready!(self.exit_rx.poll_unpin(cx)) {
+            Ok(ret) => ret,
+            // the thread catches component panics, the sender only drops if reporting itself panicked
+            Err(_) => (
+                ComponentExit::Panic("component thread exited without report".to_string()),
+                0,
+                self.started.elapsed(),
+            ),
+        };
+        let report = ComponentReport {
+            name: self.name.take().expect("component handle already resolved"),
+            exit,
+            restarts,
+            elapsed,
+        };
+        Poll::Ready((self.index, report))
+    }
+}
+
+/// Resolve once every component exited or the grace timeout expired after shutdown.
+///
+/// Component exits are delivered by the component threads, so awaiting the Game never
+/// blocks the executor and it can be `select!`ed with other futures.
 #[derive(Debug)]
 #[pin_project]
 pub struct Game<N>
 where
     N: Send + Debug,
 {
-    component_handles: VecDeque<ComponentHandle<N>>,
-    poll_component: Option<ComponentHandle<N>>,
-    ctrl_c_future: JoinHandle<()>,
-    ctrl_c_trigger: bool,
+    component_handles: FuturesUnordered<ComponentHandle<N>>,
+    reports: Vec<Option<ComponentReport<N>>>,
+    // handles of launched components, including those spawned through GameHandle
+    launched: mpsc::UnboundedReceiver<ComponentHandle<N>>,
+    shutdown: ShutdownHandle,
+    // send shutdown to the components stage by stage
+    shutdown_future: JoinHandle<()>,
+    shutdown_wait: Pin<Box<WaitForCancellationFutureOwned>>,
+    shutdown_trigger: bool,
+    grace_timeout: Option<Duration>,
+    grace: Option<Pin<Box<tokio::time::Sleep>>>,
+    // runtimes of Shared and Pool components
+    runtimes: Arc<Mutex<runtime::Runtimes>>,
+    states: state::States<N>,
+    metrics: Metrics<N>,
+}
+
+impl<N> Game<N>
+where
+    N: Send + Debug,
+{
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+}
+
+impl<N> Game<N>
+where
+    N: Send + Debug + Clone + Eq,
+{
+    /// current state of every component in launch order
+    pub fn states(&self) -> Vec<(N, ComponentState)> {
+        self.states.snapshot()
+    }
+
+    /// receiver notified on every state change of the component `name`.
+    /// None if no component of that name was launched
+    pub fn watch_state(&self, name: &N) -> Option<watch::Receiver<ComponentState>> {
+        self.states.get(name).map(|state| state.subscribe())
+    }
+}
+
+impl<N> Game<N>
+where
+    N: Send + Debug + Clone + Eq + std::hash::Hash,
+{
+    /// Queue depth, traffic per sender and call latency of every routed mailbox.
+    /// Counters are cumulative, see [`MetricsSnapshot::rates`] for messages per second.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot<N> {
+        self.metrics.snapshot()
+    }
 }
 
 impl<N> Future for Game<N>
 where
     N: Send + Debug,
 {
-    type Output = ();
+    type Output = GameReport<N>;
 
     fn poll(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
         let this = self.project();
-        if !*this.ctrl_c_trigger {
-            match ready!(this.ctrl_c_future.poll_unpin(cx)) {
-                Ok(_) => *this.ctrl_c_trigger = true,
-                Err(err) => tracing::error!("ctrl_c polling error: {}", err),
-            };
-            this.poll_component
-                .replace(this.component_handles.pop_front().unwrap());
+        if !*this.shutdown_trigger && this.shutdown_wait.as_mut().poll(cx).is_ready() {
+            *this.shutdown_trigger = true;
+            *this.grace = this
+                .grace_timeout
+                .map(|timeout| Box::pin(tokio::time::sleep(timeout)));
+        }
+        while let Poll::Ready(Some(mut handle)) = this.launched.poll_recv(cx) {
+            handle.index = this.reports.len();
+            this.reports.push(None);
+            this.component_handles.push(handle);
         }
-        while let Some(handle) = this.poll_component.take() {
-            // match ready!(handle
-            //     .join
-            //     .join()
-            //     .expect("handle's thread join error")
-            //     .poll_unpin(cx))
-            // {
-            //     Ok(_) => tracing::info!("[{:?}] join success", handle.name),
-            //     Err(err) => tracing::error!("error occur while wait for component join: {}", err),
-            // }
-            match handle.join.join().expect("component thread join error") {
-                Ok(_) => tracing::info!("[{:?}] join success", handle.name),
-                Err(err) => tracing::error!("error occur while wait for component join: {}", err),
+        while let Poll::Ready(Some((index, report))) = this.component_handles.poll_next_unpin(cx) {
+            match &report.exit {
+                ComponentExit::Ok => tracing::info!("[{:?}] join success", report.name),
+                ComponentExit::NotStarted => {
+                    tracing::info!("[{:?}] not started before shutdown", report.name)
+                }
+                exit => tracing::error!(
+                    "error occur while wait for component[{:?}] join: {}",
+                    report.name,
+                    exit
+                ),
             }
-
-            // shutdown component's runtime
-            // handle.rt.shutdown_background();
-            *this.poll_component = this.component_handles.pop_front();
+            this.reports[index] = Some(report);
+        }
+        if this.component_handles.is_empty() {
+            if !*this.shutdown_trigger {
+                tracing::debug!("all components exited before shutdown");
+                this.shutdown_future.abort();
+                *this.shutdown_trigger = true;
+            }
+        } else {
+            let expired = match this.grace.as_mut() {
+                Some(grace) => grace.as_mut().poll(cx).is_ready(),
+                None => false,
+            };
+            if !expired {
+                return Poll::Pending;
+            }
+            for handle in std::mem::take(this.component_handles) {
+                let (index, report) = handle.abandon();
+                tracing::warn!(
+                    "[{:?}] still running after grace timeout {:?}, abandoned",
+                    report.name,
+                    this.grace_timeout.unwrap_or_default()
+                );
+                this.reports[index] = Some(report);
+            }
+            // the staged shutdown may still wait on the abandoned components
+            this.shutdown_future.abort();
         }
-        std::task::Poll::Ready(())
+        // no more component can be spawned
+        this.launched.close();
+        // components still running on the shared runtimes are dropped with them
+        drop(std::mem::take(
+            &mut *this.runtimes.lock().unwrap_or_else(PoisonError::into_inner),
+        ));
+        Poll::Ready(GameReport {
+            components: this.reports.drain(..).flatten().collect(),
+        })
     }
 }
@@ -0,0 +1,86 @@
+use std::{any::Any, error::Error as StdError, fmt::Debug, time::Duration};
+
+/// how a component finished
+#[derive(Debug)]
+pub enum ComponentExit {
+    /// `init` and `run` both returned Ok
+    Ok,
+    /// `init` or `run` returned an error
+    Err(Box<dyn StdError + Send>),
+    /// the component panicked, holds the panic message
+    Panic(String),
+    /// still running when the grace timeout expired
+    Abandoned,
+    /// shutdown began while the component waited for its dependencies, it never ran
+    NotStarted,
+}
+
+impl ComponentExit {
+    /// true unless the component failed, a component that never started did not fail
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok | Self::NotStarted)
+    }
+}
+
+impl std::fmt::Display for ComponentExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Err(err) => write!(f, "error: {}", err),
+            Self::Panic(msg) => write!(f, "panic: {}", msg),
+            Self::Abandoned => write!(f, "abandoned"),
+            Self::NotStarted => write!(f, "not started"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ComponentReport<N> {
+    pub name: N,
+    pub exit: ComponentExit,
+    /// times the supervisor restarted the component
+    pub restarts: u32,
+    /// how long the component ran
+    pub elapsed: Duration,
+}
+
+/// output of [`super::Game`], one entry per component in registration order
+#[derive(Debug)]
+pub struct GameReport<N> {
+    pub components: Vec<ComponentReport<N>>,
+}
+
+impl<N> GameReport<N> {
+    /// true if no component failed
+    pub fn is_ok(&self) -> bool {
+        self.components.iter().all(|c| c.exit.is_ok())
+    }
+
+    /// components that returned an error, panicked or were abandoned
+    pub fn failures(&self) -> impl Iterator<Item = &ComponentReport<N>> {
+        self.components.iter().filter(|c| !c.exit.is_ok())
+    }
+}
+
+impl<N: Debug> std::fmt::Display for GameReport<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in &self.components {
+            write!(f, "[{:?}] {} after {:?}", c.name, c.exit, c.elapsed)?;
+            if c.restarts > 0 {
+                write!(f, ", restarted {} times", c.restarts)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::Error;
+use futures::Future;
+use std::collections::HashMap;
+use tokio::runtime::{Handle, Runtime};
+
+/// Where a component runs, chosen by [`crate::component::ComponentBuilder::runtime_mode`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum RuntimeMode {
+    /// own OS thread driving the runtime from `ComponentBuilder::runtime`
+    #[default]
+    Dedicated,
+    /// task on the multi-thread runtime shared by the whole game
+    Shared,
+    /// task on a named runtime pool registered through `GameBuilder::runtime_pool`
+    Pool(String),
+}
+
+// worker threads of the shared runtime and named pools. None uses tokio's default
+#[derive(Debug, Default)]
+pub(crate) struct PoolConfig {
+    pub(crate) shared: Option<usize>,
+    pub(crate) named: HashMap<String, Option<usize>>,
+    // run every component as a task on the runtime calling serve, ignoring RuntimeMode
+    pub(crate) current: bool,
+}
+
+// multi-thread runtimes owned by a game, built on first use
+#[derive(Debug, Default)]
+pub(crate) struct Runtimes {
+    runtimes: HashMap<RuntimeMode, Runtime>,
+}
+
+impl Runtimes {
+    /// handle of the runtime for a Shared or Pool component
+    pub(crate) fn handle(
+        &mut self,
+        mode: &RuntimeMode,
+        config: &PoolConfig,
+    ) -> Result<Handle, Error> {
+        if let Some(rt) = self.runtimes.get(mode) {
+            return Ok(rt.handle().clone());
+        }
+        let (thread_name, worker_threads) = match mode {
+            RuntimeMode::Dedicated => unreachable!("dedicated component has no shared runtime"),
+            RuntimeMode::Shared => ("gsfw-shared".to_string(), config.shared),
+            RuntimeMode::Pool(name) => match config.named.get(name) {
+                Some(worker_threads) => (format!("gsfw-{}", name), *worker_threads),
+                None => return Err(Error::UnknownRuntimePool(name.clone())),
+            },
+        };
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.thread_name(thread_name).enable_all();
+        if let Some(worker_threads) = worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        let rt = builder.build()?;
+        let handle = rt.handle().clone();
+        self.runtimes.insert(mode.clone(), rt);
+        Ok(handle)
+    }
+}
+
+impl Drop for Runtimes {
+    // the game is usually dropped inside an async context, where blocking shutdown panics
+    fn drop(&mut self) {
+        for (_, rt) in self.runtimes.drain() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+// how a component driver is launched
+pub(crate) enum Executor {
+    // detached thread blocking on the component's own runtime
+    Thread(Runtime),
+    // task on a shared runtime
+    Task(Handle),
+}
+
+impl Executor {
+    pub(crate) fn spawn<F>(self, driver: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self {
+            Self::Thread(rt) => {
+                std::thread::spawn(move || {
+                    rt.block_on(driver);
+                    rt.shutdown_background();
+                });
+            }
+            Self::Task(handle) => {
+                handle.spawn(driver);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PoolConfig, RuntimeMode, Runtimes};
+    use crate::error::Error;
+    use tokio::runtime::Handle;
+
+    // name of a worker thread of the runtime behind `handle`
+    fn thread_name(handle: &Handle) -> String {
+        let task = handle.spawn(async { std::thread::current().name().map(String::from) });
+        handle.block_on(task).unwrap().unwrap()
+    }
+
+    #[test]
+    fn resolve_runtimes() {
+        let config = PoolConfig {
+            shared: Some(1),
+            named: [("db".to_string(), Some(2))].into(),
+            current: false,
+        };
+        let mut runtimes = Runtimes::default();
+        // nothing is built before a component asks for it
+        assert!(runtimes.runtimes.is_empty());
+
+        let shared = runtimes.handle(&RuntimeMode::Shared, &config).unwrap();
+        assert_eq!(thread_name(&shared), "gsfw-shared");
+        assert_eq!(shared.metrics().num_workers(), 1);
+        let db = RuntimeMode::Pool("db".to_string());
+        let pool = runtimes.handle(&db, &config).unwrap();
+        assert_eq!(thread_name(&pool), "gsfw-db");
+        assert_eq!(pool.metrics().num_workers(), 2);
+
+        // later components join the runtimes already built
+        runtimes.handle(&RuntimeMode::Shared, &config).unwrap();
+        runtimes.handle(&db, &config).unwrap();
+        assert_eq!(runtimes.runtimes.len(), 2);
+
+        let chat = RuntimeMode::Pool("chat".to_string());
+        assert!(matches!(
+            runtimes.handle(&chat, &config),
+            Err(Error::UnknownRuntimePool(name)) if name == "chat"
+        ));
+        assert_eq!(runtimes.runtimes.len(), 2);
+    }
+}
@@ -0,0 +1,95 @@
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// Cloneable trigger of the game shutdown.
+///
+/// Every clone refers to the same game. Calling [`ShutdownHandle::shutdown`] from any
+/// component, admin command or signal listener starts the same clean up procedure as CTRL+C.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    token: CancellationToken,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// request the game to shutdown. calling it more than once has no extra effect
+    pub fn shutdown(&self) {
+        self.token.cancel()
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// resolve once shutdown has been requested
+    pub async fn wait(&self) {
+        self.token.cancelled().await
+    }
+
+    pub(crate) fn wait_owned(&self) -> WaitForCancellationFutureOwned {
+        self.token.clone().cancelled_owned()
+    }
+}
+
+/// wait for SIGINT, SIGTERM or SIGHUP, return the name of the received signal
+#[cfg(unix)]
+pub(crate) async fn recv_signal() -> &'static str {
+    use tokio::signal::unix::SignalKind;
+
+    tokio::select! {
+        _ = ctrl_c() => "SIGINT",
+        _ = unix_signal(SignalKind::terminate(), "SIGTERM") => "SIGTERM",
+        _ = unix_signal(SignalKind::hangup(), "SIGHUP") => "SIGHUP",
+    }
+}
+
+/// wait for CTRL+C, return the name of the received signal
+#[cfg(not(unix))]
+pub(crate) async fn recv_signal() -> &'static str {
+    ctrl_c().await;
+    "CTRL+C"
+}
+
+// A signal that cannot be listened to is logged and never resolves, so the others still
+// trigger shutdown
+async fn ctrl_c() {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        tracing::error!("fail to listen CTRL+C. {}", err);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(unix)]
+async fn unix_signal(kind: tokio::signal::unix::SignalKind, name: &str) {
+    match tokio::signal::unix::signal(kind) {
+        Ok(mut signal) => {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        Err(err) => tracing::error!("fail to listen {}. {}", name, err),
+    }
+    std::future::pending::<()>().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::ShutdownHandle;
+
+    #[tokio::test]
+    async fn every_clone_sees_shutdown() {
+        let handle = ShutdownHandle::new();
+        let waiting = handle.clone();
+        let waiter = tokio::spawn(async move { waiting.wait().await });
+        assert!(!handle.is_shutdown());
+
+        handle.clone().shutdown();
+        handle.shutdown();
+        waiter.await.unwrap();
+        assert!(handle.is_shutdown());
+        // resolves at once after the shutdown
+        handle.wait_owned().await;
+    }
+}
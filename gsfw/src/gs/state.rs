@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::watch;
+
+/// Lifecycle of a component, observable through [`super::GameHandle::watch_state`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComponentState {
+    /// builder is set up, waiting for dependencies or a restart backoff
+    Building,
+    /// running `init`
+    Initializing,
+    /// `init` complete, running `run`
+    Running,
+    /// shutdown message sent, waiting for `run` to return
+    ShuttingDown,
+    /// done for good, Err holds the display of the last exit
+    Stopped(Result<(), String>),
+}
+
+impl ComponentState {
+    pub fn is_running(&self) -> bool {
+        matches!(self, Self::Running)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        matches!(self, Self::Stopped(_))
+    }
+}
+
+/// move `state` from `from` to `to`, leave it untouched if it moved elsewhere meanwhile
+pub(crate) fn transit(
+    state: &watch::Sender<ComponentState>,
+    from: ComponentState,
+    to: ComponentState,
+) -> bool {
+    state.send_if_modified(|state| {
+        if *state != from {
+            return false;
+        }
+        *state = to;
+        true
+    })
+}
+
+/// mark the component as shutting down. false if it is already shutting down or stopped
+pub(crate) fn begin_shutdown(state: &watch::Sender<ComponentState>) -> bool {
+    state.send_if_modified(|state| match state {
+        ComponentState::ShuttingDown | ComponentState::Stopped(_) => false,
+        _ => {
+            *state = ComponentState::ShuttingDown;
+            true
+        }
+    })
+}
+
+// a component launched by the game
+#[derive(Debug)]
+struct Launched<N> {
+    name: N,
+    state: watch::Sender<ComponentState>,
+    // spawned through GameHandle after serve
+    dynamic: bool,
+}
+
+type Shared<N> = Arc<Mutex<Vec<Launched<N>>>>;
+
+/// Latest state of every component in launch order, shared by the launcher and the Game
+#[derive(Debug)]
+pub(crate) struct States<N>(Shared<N>);
+
+impl<N> Clone for States<N> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<N> Default for States<N> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<N: Clone + Eq> States<N> {
+    /// track a new component. replaces the entry of a retired component of the same name
+    pub(crate) fn insert(&self, name: N, dynamic: bool) -> watch::Sender<ComponentState> {
+        let (state, _) = watch::channel(ComponentState::Building);
+        let mut launched = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        launched.retain(|l| l.name != name);
+        launched.push(Launched {
+            name,
+            state: state.clone(),
+            dynamic,
+        });
+        state
+    }
+
+    pub(crate) fn get(&self, name: &N) -> Option<watch::Sender<ComponentState>> {
+        let launched = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        launched
+            .iter()
+            .find(|l| l.name == *name)
+            .map(|l| l.state.clone())
+    }
+
+    /// stop tracking the launch owning `state`, a respawned component of the same name stays
+    pub(crate) fn remove(&self, name: &N, state: &watch::Sender<ComponentState>) {
+        let mut launched = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        launched.retain(|l| l.name != *name || !l.state.same_channel(state));
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<(N, ComponentState)> {
+        let launched = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        launched
+            .iter()
+            .map(|l| (l.name.clone(), l.state.borrow().clone()))
+            .collect()
+    }
+
+    /// Order in which components are shutdown. Components spawned at runtime go first,
+    /// latest first, then the registered components by reversed startup `stages`.
+    pub(crate) fn shutdown_stages(
+        &self,
+        stages: &[Vec<N>],
+    ) -> Vec<Vec<(N, watch::Sender<ComponentState>)>> {
+        let launched = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let dynamic = launched
+            .iter()
+            .rev()
+            .filter(|l| l.dynamic)
+            .map(|l| vec![(l.name.clone(), l.state.clone())]);
+        let registered = stages.iter().rev().map(|stage| {
+            stage
+                .iter()
+                .filter_map(|name| launched.iter().find(|l| !l.dynamic && l.name == *name))
+                .map(|l| (l.name.clone(), l.state.clone()))
+                .collect()
+        });
+        dynamic.chain(registered).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{begin_shutdown, transit, ComponentState, States};
+
+    #[test]
+    fn shutdown_is_not_overwritten() {
+        let states = States::default();
+        let state = states.insert("a", false);
+        assert!(transit(
+            &state,
+            ComponentState::Building,
+            ComponentState::Initializing
+        ));
+        assert!(begin_shutdown(&state));
+        assert!(!begin_shutdown(&state));
+        // init completing late keeps the component shutting down
+        assert!(!transit(
+            &state,
+            ComponentState::Initializing,
+            ComponentState::Running
+        ));
+        assert_eq!(states.snapshot(), vec![("a", ComponentState::ShuttingDown)]);
+    }
+
+    #[test]
+    fn dynamic_components_stop_first() {
+        let states = States::default();
+        states.insert("db", false);
+        states.insert("player", false);
+        states.insert("bot", true);
+        states.insert("gm", true);
+        let order: Vec<Vec<_>> = states
+            .shutdown_stages(&[vec!["db"], vec!["player"]])
+            .into_iter()
+            .map(|stage| stage.into_iter().map(|(name, _)| name).collect())
+            .collect();
+        assert_eq!(
+            order,
+            vec![vec!["gm"], vec!["bot"], vec!["player"], vec!["db"]]
+        );
+    }
+
+    #[test]
+    fn remove_keeps_respawned() {
+        let states = States::default();
+        let retired = states.insert("a", true);
+        let respawned = states.insert("a", true);
+        states.remove(&"a", &retired);
+        assert_eq!(states.snapshot().len(), 1);
+        states.remove(&"a", &respawned);
+        assert!(states.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use super::{
+    launcher::ExitHooks,
+    report::panic_message,
+    state::{transit, ComponentState, States},
+    ComponentExit, ShutdownHandle,
+};
+use crate::{
+    chanrpc::{broker::Broker, MailboxSlot, Router},
+    component::{Component, ComponentBuilder},
+};
+use futures::FutureExt;
+use std::{collections::VecDeque, panic::AssertUnwindSafe, time::Duration};
+use tokio::{sync::watch, time::Instant};
+
+/// What to do when a component's `init` or `run` returns an error or panics.
+/// A component returning Ok is never restarted.
+#[derive(Debug, Clone, Default)]
+pub enum Supervisor {
+    /// leave the component stopped. default of [`super::GameBuilder::component`]
+    #[default]
+    Ignore,
+    /// shutdown the whole game
+    Escalate,
+    /// build a fresh component from the factory, keeping its channel
+    Restart(RestartPolicy),
+}
+
+/// One-for-one restart with exponential backoff
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    backoff: Duration,
+    max_backoff: Duration,
+    max_restarts: u32,
+    window: Duration,
+    escalate: bool,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+            escalate: false,
+        }
+    }
+}
+
+impl RestartPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// delay before the first restart, doubled on each restart inside the window up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// give up once the component restarted `max_restarts` times within `window`
+    pub fn max_restarts(mut self, max_restarts: u32, window: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.window = window;
+        self
+    }
+
+    /// shutdown the whole game after giving up instead of leaving the component stopped
+    pub fn escalate(mut self, escalate: bool) -> Self {
+        self.escalate = escalate;
+        self
+    }
+
+    fn delay(&self, attempt: usize) -> Duration {
+        let factor = 1u32 << attempt.min(16);
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+pub(crate) type BuilderFactory<B> = Box<dyn Fn() -> Box<dyn ComponentBuilder<B>> + Send>;
+
+// drive one component through init and run, applying its supervisor policy
+pub(crate) struct Supervised<B: Broker> {
+    pub(crate) name: B::Name,
+    pub(crate) supervisor: Supervisor,
+    pub(crate) factory: Option<BuilderFactory<B>>,
+    pub(crate) mailbox: MailboxSlot<B::Proto, B::Name, B::Err>,
+    pub(crate) router: Router<B::Proto, B::Name, B::Err>,
+    pub(crate) shutdown: ShutdownHandle,
+    pub(crate) state: watch::Sender<ComponentState>,
+    pub(crate) states: States<B::Name>,
+    pub(crate) dependencies: Vec<(B::Name, watch::Receiver<ComponentState>)>,
+    pub(crate) exit_hooks: ExitHooks<B::Name>,
+}
+
+impl<B: Broker + 'static> Supervised<B> {
+    /// run until the component is done for good. return its last exit and restart count
+    pub(crate) async fn run(mut self, component: Box<dyn Component<B>>) -> (ComponentExit, u32) {
+        let (exit, restarts) = self.supervise(component).await;
+        self.mailbox.close();
+        self.exit_hooks.run(&self.name);
+        self.state
+            .send_replace(ComponentState::Stopped(match &exit {
+                ComponentExit::Ok | ComponentExit::NotStarted => Ok(()),
+                exit => Err(exit.to_string()),
+            }));
+        // nothing reaches a retired component anymore, stop tracking it
+        if self.router.forget_retired(&self.name) {
+            self.states.remove(&self.name, &self.state);
+        }
+        (exit, restarts)
+    }
+
+    async fn supervise(&mut self, mut component: Box<dyn Component<B>>) -> (ComponentExit, u32) {
+        let mut restarts = 0;
+        let mut history: VecDeque<Instant> = VecDeque::new();
+        loop {
+            // a failed dependency is final, restarting would not help
+            if let Some(exit) = self.wait_dependencies().await {
+                return (exit, restarts);
+            }
+            let state = &self.state;
+            transit(
+                state,
+                ComponentState::Building,
+                ComponentState::Initializing,
+            );
+            let exit = match AssertUnwindSafe(async move {
+                let component = component.init().await?;
+                // shutdown may have begun during init
+                transit(state, ComponentState::Initializing, ComponentState::Running);
+                component.run().await
+            })
+            .catch_unwind()
+            .await
+            {
+                Ok(Ok(_)) => ComponentExit::Ok,
+                Ok(Err(err)) => ComponentExit::Err(err),
+                Err(payload) => ComponentExit::Panic(panic_message(payload)),
+            };
+            if exit.is_ok() || self.shutdown.is_shutdown() || self.router.is_retired(&self.name) {
+                return (exit, restarts);
+            }
+            let policy = match &self.supervisor {
+                Supervisor::Ignore => return (exit, restarts),
+                Supervisor::Escalate => {
+                    tracing::error!("component {:?} {}, escalate to shutdown", self.name, exit);
+                    self.shutdown.shutdown();
+                    return (exit, restarts);
+                }
+                Supervisor::Restart(policy) => policy,
+            };
+
+            let now = Instant::now();
+            while matches!(history.front(), Some(at) if now.duration_since(*at) > policy.window) {
+                history.pop_front();
+            }
+            if history.len() >= policy.max_restarts as usize {
+                tracing::error!(
+                    "component {:?} {}, restarted {} times in {:?}, give up",
+                    self.name,
+                    exit,
+                    history.len(),
+                    policy.window
+                );
+                if policy.escalate {
+                    self.shutdown.shutdown();
+                }
+                return (exit, restarts);
+            }
+            let delay = policy.delay(history.len());
+            tracing::warn!("component {:?} {}, restart in {:?}", self.name, exit, delay);
+            self.state.send_replace(ComponentState::Building);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => (),
+                _ = self.shutdown.wait() => return (exit, restarts),
+            }
+            component = match self.rebuild() {
+                Some(component) => component,
+                None => return (exit, restarts),
+            };
+            history.push_back(Instant::now());
+            restarts += 1;
+        }
+    }
+
+    // wait until every dependency completed init.
+    // Some if the component must not start, either shutdown began or a dependency stopped
+    async fn wait_dependencies(&mut self) -> Option<ComponentExit> {
+        for (name, mut state) in self.dependencies.clone() {
+            tracing::debug!("component {:?} waiting for {:?}", self.name, name);
+            let running = tokio::select! {
+                ret = state.wait_for(|state| {
+                    !matches!(state, ComponentState::Building | ComponentState::Initializing)
+                }) => ret.map(|state| state.is_running()).unwrap_or(false),
+                _ = self.shutdown.wait() => return Some(ComponentExit::NotStarted),
+            };
+            if !running {
+                tracing::error!("dependency {:?} of {:?} stopped", name, self.name);
+                return Some(ComponentExit::Err(Box::new(
+                    crate::error::Error::DependencyStopped(format!("{:?}", name)),
+                )));
+            }
+        }
+        None
+    }
+
+    fn rebuild(&self) -> Option<Box<dyn Component<B>>> {
+        let factory = self.factory.as_ref()?;
+        let mailbox = match self.mailbox.reclaim() {
+            Some(mailbox) => mailbox,
+            None => {
+                tracing::error!(
+                    "mailbox of component {:?} is still held, unable to restart",
+                    self.name
+                );
+                return None;
+            }
+        };
+        let mut builder = factory();
+        builder.set_broker(B::new(self.name.clone(), &self.router));
+        builder.set_rx(mailbox);
+        let component = builder.build();
+        tracing::info!("component {:?} rebuilt", self.name);
+        Some(component)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RestartPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn restart_delay_doubles_until_max() {
+        let policy =
+            RestartPolicy::new().backoff(Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(500));
+        assert_eq!(policy.delay(100), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,478 @@
+use super::{Game, GameBuilder, GameHandle, GameReport, ShutdownHandle};
+use crate::{chanrpc::broker::Broker, error::Error};
+use std::{pin::Pin, time::Duration};
+
+const MAX_SETTLE_ROUNDS: usize = 1024;
+
+/// Serve a whole game deterministically inside a test.
+///
+/// Every component runs as a task on the current-thread runtime of the test, whatever its
+/// `RuntimeMode`, and the clock is paused. Timers only fire when the test advances virtual
+/// time, so multi-component scenarios replay the same way on every run.
+///
+/// ```ignore
+/// #[tokio::test]
+/// async fn daily_reset() {
+///     let mut game = TestGame::serve(GameBuilder::new().component(..)).unwrap();
+///     game.broker(Name::Gm).cast(Name::Player, Proto::DailyReset).await.unwrap();
+///     game.advance(Duration::from_secs(60)).await;
+///     assert!(game.shutdown().await.is_ok());
+/// }
+/// ```
+pub struct TestGame<B: Broker> {
+    game: Pin<Box<Game<B::Name>>>,
+    handle: GameHandle<B>,
+    shutdown: ShutdownHandle,
+}
+
+impl<B: Broker + 'static> TestGame<B> {
+    /// Serve the game on the current runtime and pause its clock. Signals are not handled.
+    /// Must be called inside a current-thread runtime whose clock is not paused yet,
+    /// e.g. a plain `#[tokio::test]`.
+    pub fn serve(builder: GameBuilder<B>) -> Result<Self, Error> {
+        tokio::time::pause();
+        let handle = builder.game_handle();
+        let shutdown = builder.shutdown_handle();
+        let game = builder.handle_signals(false).current_runtime().serve()?;
+        Ok(Self {
+            game: Box::pin(game),
+            handle,
+            shutdown,
+        })
+    }
+
+    /// broker sending as `name`, to inject casts and calls into any component
+    pub fn broker(&self, name: B::Name) -> B {
+        self.handle.broker(name).expect("test game is serving")
+    }
+
+    /// handle to spawn, retire and inspect components
+    pub fn handle(&self) -> GameHandle<B> {
+        self.handle.clone()
+    }
+
+    /// Move virtual time forward by `duration`. Every component runs until idle before each
+    /// timer fires, so timers are observed in deadline order. Timers due exactly at the end
+    /// of `duration` may fire before or after this returns.
+    pub async fn advance(&mut self, duration: Duration) {
+        // with the clock paused, the runtime jumps to the next timer once every task is idle
+        tokio::time::sleep(duration).await;
+    }
+
+    /// let every component process its queued messages without moving time
+    pub async fn settle(&mut self) {
+        // a message handled in one round may queue another one for the next round
+        for _ in 0..MAX_SETTLE_ROUNDS {
+            tokio::task::yield_now().await;
+            if self.handle.queued().unwrap_or(0) == 0 {
+                break;
+            }
+        }
+        tokio::task::yield_now().await;
+    }
+
+    /// trigger the shutdown and wait for every component to stop
+    pub async fn shutdown(mut self) -> GameReport<B::Name> {
+        self.shutdown.shutdown();
+        self.game.as_mut().await
+    }
+
+    /// wait for every component to exit on its own
+    pub async fn join(mut self) -> GameReport<B::Name> {
+        self.game.as_mut().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TestGame;
+    use crate::{
+        chanrpc::{
+            broker::Broker,
+            test_util::{Bk, N, P},
+            CastError, Mailbox,
+        },
+        component::{Component, ComponentBuilder},
+        error::Error,
+        gs::{ComponentExit, ComponentState, GameBuilder, RestartPolicy, Supervisor},
+    };
+    use async_trait::async_trait;
+    use std::{error::Error as StdError, time::Duration};
+
+    // ticker casts Tick to the counter every second, counter answers Get with the count
+    struct Comp {
+        name: N,
+        rx: Mailbox<P, N, ()>,
+        broker: Bk,
+        count: u32,
+    }
+
+    #[async_trait]
+    impl Component<Bk> for Comp {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        async fn init(self: Box<Self>) -> Result<Box<dyn Component<Bk>>, Box<dyn StdError + Send>> {
+            Ok(self)
+        }
+
+        async fn run(mut self: Box<Self>) -> Result<(), Box<dyn StdError + Send>> {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick(), if self.name == N::Ticker => {
+                        self.broker.cast(N::Counter, P::Tick).await.ok();
+                    }
+                    ctx = self.rx.recv() => match ctx {
+                        None => return Ok(()),
+                        Some(ctx) => match ctx.into_parts() {
+                            (P::Shutdown, _) => return Ok(()),
+                            (P::Tick, _) => self.count += 1,
+                            (P::Get, responder) => responder.ok(P::Count(self.count)),
+                            _ => (),
+                        },
+                    },
+                }
+            }
+        }
+    }
+
+    struct CompBuilder {
+        name: N,
+        rx: Option<Mailbox<P, N, ()>>,
+        broker: Option<Bk>,
+    }
+
+    impl CompBuilder {
+        fn new(name: N) -> Self {
+            Self {
+                name,
+                rx: None,
+                broker: None,
+            }
+        }
+    }
+
+    impl ComponentBuilder<Bk> for CompBuilder {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        fn build(self: Box<Self>) -> Box<dyn Component<Bk>> {
+            Box::new(Comp {
+                name: self.name,
+                rx: self.rx.unwrap(),
+                broker: self.broker.unwrap(),
+                count: 0,
+            })
+        }
+
+        fn set_rx(&mut self, rx: Mailbox<P, N, ()>) {
+            self.rx = Some(rx)
+        }
+
+        fn set_broker(&mut self, broker: Bk) {
+            self.broker = Some(broker)
+        }
+
+        fn dependencies(&self) -> Vec<N> {
+            match self.name {
+                N::Ticker => vec![N::Counter],
+                _ => vec![],
+            }
+        }
+    }
+
+    enum Fault {
+        // ignores the shutdown message
+        Hang,
+        // never completes init
+        Stall,
+        Panic,
+        Init,
+    }
+
+    // component and builder at once, misbehaving as told
+    struct Faulty {
+        name: N,
+        fault: Fault,
+        rx: Option<Mailbox<P, N, ()>>,
+    }
+
+    impl Faulty {
+        fn new(name: N, fault: Fault) -> Self {
+            Self {
+                name,
+                fault,
+                rx: None,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Component<Bk> for Faulty {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        async fn init(self: Box<Self>) -> Result<Box<dyn Component<Bk>>, Box<dyn StdError + Send>> {
+            match self.fault {
+                Fault::Init => Err(Box::new(std::io::Error::other("no config"))),
+                Fault::Stall => std::future::pending().await,
+                _ => Ok(self),
+            }
+        }
+
+        async fn run(self: Box<Self>) -> Result<(), Box<dyn StdError + Send>> {
+            match self.fault {
+                Fault::Panic => panic!("out of bounds"),
+                _ => std::future::pending().await,
+            }
+        }
+    }
+
+    impl ComponentBuilder<Bk> for Faulty {
+        fn name(&self) -> N {
+            self.name.clone()
+        }
+
+        fn build(self: Box<Self>) -> Box<dyn Component<Bk>> {
+            self
+        }
+
+        fn set_rx(&mut self, rx: Mailbox<P, N, ()>) {
+            self.rx = Some(rx)
+        }
+
+        fn set_broker(&mut self, _: Bk) {}
+    }
+
+    async fn count(game: &TestGame<Bk>) -> u32 {
+        match game.broker(N::Test).call(N::Counter, P::Get).await {
+            Ok(P::Count(count)) => count,
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[tokio::test]
+    async fn advance_virtual_time() {
+        let builder = GameBuilder::new()
+            .component(CompBuilder::new(N::Ticker))
+            .component(CompBuilder::new(N::Counter));
+        let mut game = TestGame::serve(builder).unwrap();
+        game.settle().await;
+        assert!(game
+            .handle()
+            .states()
+            .unwrap()
+            .iter()
+            .all(|(_, state)| *state == ComponentState::Running));
+
+        game.advance(Duration::from_millis(10_500)).await;
+        assert_eq!(count(&game).await, 10);
+
+        game.broker(N::Test)
+            .cast(N::Counter, P::Tick)
+            .await
+            .unwrap();
+        game.settle().await;
+        assert_eq!(count(&game).await, 11);
+
+        let report = game.shutdown().await;
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.components.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn mailbox_metrics() {
+        let builder = GameBuilder::new()
+            .component(CompBuilder::new(N::Ticker))
+            .component(CompBuilder::new(N::Counter));
+        let mut game = TestGame::serve(builder).unwrap();
+        game.settle().await;
+        let earlier = game.handle().metrics_snapshot().unwrap();
+        game.advance(Duration::from_millis(4_500)).await;
+        assert_eq!(count(&game).await, 4);
+
+        let snapshot = game.handle().metrics_snapshot().unwrap();
+        let counter = snapshot.mailbox(&N::Counter).unwrap();
+        assert_eq!(counter.depth, 0);
+        assert!(counter.peak_depth >= 1);
+        let ticker = counter
+            .senders
+            .iter()
+            .find(|p| p.from == N::Ticker)
+            .unwrap();
+        assert_eq!((ticker.casts, ticker.calls), (4, 0));
+        let test = counter.senders.iter().find(|p| p.from == N::Test).unwrap();
+        assert_eq!((test.casts, test.calls), (0, 1));
+        assert_eq!(test.call_latency.count(), 1);
+        let rates = snapshot.rates(&earlier);
+        let (_, _, rate) = rates
+            .iter()
+            .find(|(from, _, _)| *from == N::Ticker)
+            .unwrap();
+        assert!((rate - 4.0 / 4.5).abs() < 0.01, "{:?}", rates);
+
+        assert!(game.shutdown().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn exit_drops_subscriptions() {
+        let builder = GameBuilder::new().component(CompBuilder::new(N::Counter));
+        let bus = builder.bus::<&str>();
+        let mut game = TestGame::serve(builder).unwrap();
+        bus.subscribe("tick", N::Counter);
+        let report = bus.publish(N::Test, &"tick", P::Tick).await;
+        assert_eq!(report.sent, 1);
+        assert_eq!(count(&game).await, 1);
+
+        game.handle().retire(N::Counter).unwrap();
+        game.settle().await;
+        assert!(bus.subscribers(&"tick").is_empty());
+        assert!(game.join().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn retired_components_are_forgotten() {
+        let builder = GameBuilder::new()
+            .component(CompBuilder::new(N::Counter))
+            .component(CompBuilder::new(N::Ticker));
+        let mut game = TestGame::serve(builder).unwrap();
+        game.settle().await;
+        let handle = game.handle();
+        let broker = game.broker(N::Test);
+
+        handle.retire(N::Ticker).unwrap();
+        assert!(matches!(
+            broker.cast(N::Ticker, P::Tick).await,
+            Err(CastError::Route(Error::ComponentRetired(_), _))
+        ));
+        // every task runs until idle before virtual time moves
+        game.advance(Duration::from_millis(1)).await;
+        assert!(matches!(
+            broker.cast(N::Ticker, P::Tick).await,
+            Err(CastError::Route(Error::UnknownComponent(_), _))
+        ));
+        assert_eq!(handle.states().unwrap().len(), 1);
+
+        // stopped on its own before being retired
+        broker.cast(N::Counter, P::Shutdown).await.unwrap();
+        game.advance(Duration::from_millis(1)).await;
+        handle.retire(N::Counter).unwrap();
+        assert!(handle.states().unwrap().is_empty());
+        assert!(game.join().await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_handle_without_signals() {
+        let builder = GameBuilder::new()
+            .handle_signals(false)
+            .current_runtime()
+            .component(CompBuilder::new(N::Counter));
+        let shutdown = builder.shutdown_handle();
+        let mut game = Box::pin(builder.serve().unwrap());
+        // only the handle ends the game
+        let running = tokio::time::timeout(Duration::from_secs(60), game.as_mut()).await;
+        assert!(running.is_err());
+
+        shutdown.shutdown();
+        let report = game.await;
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.components.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn abandon_after_grace_timeout() {
+        let builder = GameBuilder::new()
+            .grace_timeout(Duration::from_secs(5))
+            .component(Faulty::new(N::Gate, Fault::Hang))
+            .component(CompBuilder::new(N::Counter));
+        let mut game = TestGame::serve(builder).unwrap();
+        game.settle().await;
+
+        let report = game.shutdown().await;
+        assert_eq!(report.failures().count(), 1);
+        let gate = &report.components[0];
+        assert_eq!(gate.name, N::Gate);
+        assert!(matches!(gate.exit, ComponentExit::Abandoned));
+        assert!(report.components[1].exit.is_ok());
+    }
+
+    #[tokio::test]
+    async fn report_not_started() {
+        let builder = GameBuilder::new()
+            .grace_timeout(Duration::from_secs(5))
+            .component(Faulty::new(N::Counter, Fault::Stall))
+            .component(CompBuilder::new(N::Ticker));
+        let mut game = TestGame::serve(builder).unwrap();
+        game.settle().await;
+
+        // the ticker still waits for the counter to complete init
+        let report = game.shutdown().await;
+        let ticker = &report.components[1];
+        assert_eq!(ticker.name, N::Ticker);
+        assert!(matches!(ticker.exit, ComponentExit::NotStarted));
+        assert_eq!(ticker.exit.to_string(), "not started");
+        let failed: Vec<_> = report.failures().map(|c| c.name.clone()).collect();
+        assert_eq!(failed, vec![N::Counter]);
+    }
+
+    #[tokio::test]
+    async fn report_panic_and_init_failure() {
+        let builder = GameBuilder::new()
+            .component(Faulty::new(N::Db, Fault::Panic))
+            .component(Faulty::new(N::Player, Fault::Init));
+        let game = TestGame::serve(builder).unwrap();
+
+        // both exit on their own, the supervisor leaves them stopped
+        let report = game.join().await;
+        assert!(!report.is_ok());
+        match &report.components[0] {
+            report if report.name == N::Db => {
+                assert!(matches!(&report.exit, ComponentExit::Panic(msg) if msg == "out of bounds"))
+            }
+            report => panic!("unexpected {:?}", report),
+        }
+        match &report.components[1] {
+            report if report.name == N::Player => {
+                assert_eq!(report.exit.to_string(), "error: no config")
+            }
+            report => panic!("unexpected {:?}", report),
+        }
+    }
+
+    #[tokio::test]
+    async fn restart_until_window_limit() {
+        let policy = RestartPolicy::new()
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .max_restarts(3, Duration::from_secs(60));
+        let builder = GameBuilder::new().supervised_component(Supervisor::Restart(policy), || {
+            Faulty::new(N::Player, Fault::Init)
+        });
+        let game = TestGame::serve(builder).unwrap();
+
+        let report = game.join().await;
+        let player = &report.components[0];
+        assert_eq!(player.restarts, 3);
+        assert_eq!(player.exit.to_string(), "error: no config");
+    }
+
+    #[tokio::test]
+    async fn restart_window_slides_with_virtual_time() {
+        // a failure every second never sees 3 restarts within 1.5s
+        let policy = RestartPolicy::new()
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .max_restarts(3, Duration::from_millis(1500));
+        let builder = GameBuilder::new().supervised_component(Supervisor::Restart(policy), || {
+            Faulty::new(N::Player, Fault::Init)
+        });
+        let mut game = TestGame::serve(builder).unwrap();
+
+        game.advance(Duration::from_millis(10_500)).await;
+        let report = game.shutdown().await;
+        assert_eq!(report.components[0].restarts, 10);
+    }
+}
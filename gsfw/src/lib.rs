@@ -5,10 +5,13 @@ pub mod error;
 pub mod gs;
 pub mod network;
 pub mod registry;
+// lets the tests expand the derive macros, which refer to `::gsfw`
+#[cfg(test)]
+extern crate self as gsfw;
 #[cfg(feature = "derive")]
 pub use registry::{Protocol, RegistryExt};
 #[cfg(feature = "derive")]
-pub use gsfw_derive::{Protocol, Registry};
+pub use gsfw_derive::{handlers, Broker, Name, Proto, Protocol, Registry};
 
 #[cfg(feature = "util")]
 pub mod util {